@@ -26,6 +26,9 @@ pub struct Stivale2Header {
     /// Pointer to start of a linked list.
     ///
     /// Should be cast into `*const ()`.
+    ///
+    /// Tags not provided by this crate can be chained too, as a `#[repr(C)]` struct starting with
+    /// an `identifier: u64` and a `next: *const ()`.
     pub tags: *const (),
 }
 
@@ -140,9 +143,45 @@ impl Stivale2Struct {
                 if (*_c).identifier == id {
                     return Some(current);
                 }
-                current = (*_c).next as *const ();
+                current = (*_c).next;
+            }
+        }
+    }
+
+    /// Check whether the tag list ends within `max` tags.
+    ///
+    /// Returns false if the list is longer than `max` tags, which indicates a corrupted or
+    /// cyclic list that shouldn't be trusted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Struct, Stivale2Tag};
+    ///
+    /// let last = Stivale2Tag { identifier: 2, next: core::ptr::null() };
+    /// let first = Stivale2Tag { identifier: 1, next: &last as *const _ as *const () };
+    ///
+    /// let info = Stivale2Struct {
+    ///     bootloader_brand: [0; 64],
+    ///     bootloader_version: [0; 64],
+    ///     tags: &first as *const _ as u64,
+    /// };
+    ///
+    /// assert!(info.is_list_terminated(2));
+    /// assert!(!info.is_list_terminated(1));
+    /// ```
+    pub fn is_list_terminated(&self, max: usize) -> bool {
+        let mut current = self.tags as *const ();
+
+        for _ in 0..max {
+            if current.is_null() {
+                return true;
             }
+
+            current = unsafe { (*(current as *const Stivale2Tag)).next };
         }
+
+        current.is_null()
     }
 
     /// Get a immutable reference to terminal info passed on by bootloader.
@@ -161,10 +200,7 @@ impl Stivale2Struct {
 
     /// Get framebuffer info.
     pub fn get_framebuffer<'a>(&self) -> Option<&'a Stivale2StructTagFramebuffer> {
-        let fb = match self.get_tag(STIVALE2_STRUCT_TAG_FRAMEBUFFER_ID) {
-            Some(fb) => fb,
-            None => return None,
-        };
+        let fb = self.get_tag(STIVALE2_STRUCT_TAG_FRAMEBUFFER_ID)?;
 
         let fb = fb as *const Stivale2StructTagFramebuffer;
         let fb = unsafe { &*fb };
@@ -172,6 +208,243 @@ impl Stivale2Struct {
         Some(fb)
     }
 
+    /// Get `len` bytes starting at the tag with the id `id`, e.g. to hex dump a tag this crate
+    /// doesn't know about.
+    ///
+    /// The size of an arbitrary tag can't be known, so `len` has to be supplied by the caller.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not exceed the size of the tag with the id `id`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Struct, Stivale2StructTagEpoch};
+    ///
+    /// let epoch = Stivale2StructTagEpoch {
+    ///     identifier: 0x566a7bed888e1407,
+    ///     next: 0,
+    ///     epoch: 0x1122334455667788,
+    /// };
+    /// let info = Stivale2Struct {
+    ///     bootloader_brand: [0; 64],
+    ///     bootloader_version: [0; 64],
+    ///     tags: &epoch as *const _ as u64,
+    /// };
+    ///
+    /// // The epoch tag is 24 bytes long.
+    /// let bytes = unsafe { info.tag_bytes(0x566a7bed888e1407, 24) }.unwrap();
+    /// assert_eq!(bytes[..8], 0x566a7bed888e1407u64.to_ne_bytes());
+    /// assert_eq!(bytes[16..], 0x1122334455667788u64.to_ne_bytes());
+    /// assert!(unsafe { info.tag_bytes(0x1234, 24) }.is_none());
+    /// ```
+    pub unsafe fn tag_bytes<'a>(&self, id: u64, len: usize) -> Option<&'a [u8]> {
+        let tag = self.get_tag(id)?;
+
+        Some(core::slice::from_raw_parts(tag as *const u8, len))
+    }
+
+    /// Get the RSDP info.
+    pub fn get_rsdp<'a>(&self) -> Option<&'a Stivale2StructTagRsdp> {
+        self._get(STIVALE2_STRUCT_TAG_RSDP_ID)
+    }
+
+    /// Get the address of the RSDP along with its ACPI revision.
+    ///
+    /// Returns `None` if the RSDP tag is absent or the RSDP signature is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Struct, Stivale2StructTagRsdp, STIVALE2_STRUCT_TAG_RSDP_ID};
+    ///
+    /// let mut rsdp = [0u8; 20];
+    /// rsdp[0..8].copy_from_slice(b"RSD PTR ");
+    /// rsdp[15] = 2;
+    ///
+    /// let tag = Stivale2StructTagRsdp {
+    ///     identifier: STIVALE2_STRUCT_TAG_RSDP_ID,
+    ///     next: 0,
+    ///     rsdp: rsdp.as_ptr() as u64,
+    /// };
+    /// let info = Stivale2Struct {
+    ///     bootloader_brand: [0; 64],
+    ///     bootloader_version: [0; 64],
+    ///     tags: &tag as *const _ as u64,
+    /// };
+    ///
+    /// assert_eq!(info.acpi(), Some((rsdp.as_ptr() as u64, 2)));
+    /// ```
+    pub fn acpi(&self) -> Option<(u64, u8)> {
+        let rsdp = self.get_rsdp()?.rsdp;
+        let ptr = rsdp as *const u8;
+        if ptr.is_null() {
+            return None;
+        }
+
+        let signature = unsafe { core::slice::from_raw_parts(ptr, 8) };
+        if signature != b"RSD PTR " {
+            return None;
+        }
+
+        let revision = unsafe { *ptr.add(15) };
+        Some((rsdp, revision))
+    }
+
+    /// Get the higher half direct map info.
+    pub fn get_hhdm<'a>(&self) -> Option<&'a Stivale2StructTagHhdm> {
+        self._get(STIVALE2_STRUCT_TAG_HHDM_ID)
+    }
+
+    /// Check whether the bootloader set up a higher half direct map.
+    pub fn has_hhdm(&self) -> bool {
+        self.get_hhdm().is_some()
+    }
+
+    /// Get the virtual address physical memory is direct mapped at, if the bootloader set up a
+    /// higher half direct map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Struct, Stivale2StructTagHhdm, STIVALE2_STRUCT_TAG_HHDM_ID};
+    ///
+    /// let hhdm = Stivale2StructTagHhdm {
+    ///     identifier: STIVALE2_STRUCT_TAG_HHDM_ID,
+    ///     next: 0,
+    ///     addr: 0xffff800000000000,
+    /// };
+    /// let mut info = Stivale2Struct {
+    ///     bootloader_brand: [0; 64],
+    ///     bootloader_version: [0; 64],
+    ///     tags: &hhdm as *const _ as u64,
+    /// };
+    ///
+    /// assert!(info.has_hhdm());
+    /// assert_eq!(info.hhdm_offset(), Some(0xffff800000000000));
+    ///
+    /// info.tags = 0;
+    /// assert!(!info.has_hhdm());
+    /// assert_eq!(info.hhdm_offset(), None);
+    /// ```
+    pub fn hhdm_offset(&self) -> Option<u64> {
+        Some(self.get_hhdm()?.addr)
+    }
+
+    /// Check whether the physical range of the framebuffer overlaps usable memory in `memmap`.
+    ///
+    /// The framebuffer should be in a framebuffer or reserved entry, so a true result means the
+    /// bootloader reported bad data and the allocator could hand out framebuffer memory. A
+    /// framebuffer address in the higher half direct map is translated back to a physical
+    /// address first. Returns false if there is no framebuffer.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stivale_rs::v2::{Stivale2Struct, Stivale2StructTagMemmap};
+    ///
+    /// fn entry(info: &Stivale2Struct, memmap: &Stivale2StructTagMemmap<64>) {
+    ///     assert!(!info.framebuffer_overlaps_usable(memmap), "bad framebuffer in the memory map");
+    /// }
+    /// ```
+    pub fn framebuffer_overlaps_usable<const ENTRIES_LEN: usize>(
+        &self,
+        memmap: &Stivale2StructTagMemmap<ENTRIES_LEN>,
+    ) -> bool {
+        let fb = match self.get_framebuffer() {
+            Some(fb) => fb,
+            None => return false,
+        };
+
+        let base = match self.hhdm_offset() {
+            Some(offset) if fb.framebuffer_addr >= offset => fb.framebuffer_addr - offset,
+            _ => fb.framebuffer_addr,
+        };
+        let end = base.saturating_add(fb.size());
+
+        memmap
+            .as_slice()
+            .iter()
+            .any(|e| e.is_usable() && e.base < end && base < e.base.saturating_add(e.length))
+    }
+
+    /// Compute a 64 bit FNV-1a hash of the boot configuration: the bootloader brand and version,
+    /// the command line and the memory map entries. The hash is stable across boots with the
+    /// same configuration, so kernels can use it to detect configuration changes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(info: &stivale_rs::v2::Stivale2Struct, saved_hash: u64) {
+    ///     if info.config_hash() != saved_hash {
+    ///         // The boot configuration changed, rebuild any cached state.
+    ///     }
+    /// }
+    /// ```
+    pub fn config_hash(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write(&self.bootloader_brand);
+        hash.write(&self.bootloader_version);
+
+        if let Some(cmdline) = self._get::<Stivale2StructTagCmdline>(STIVALE2_STRUCT_TAG_CMDLINE_ID)
+        {
+            let mut ptr = cmdline.cmdline as *const u8;
+
+            while !ptr.is_null() && unsafe { *ptr } != 0 {
+                hash.write(&[unsafe { *ptr }]);
+                ptr = unsafe { ptr.add(1) };
+            }
+        }
+        // Terminate the command line so it can't run into the memory map.
+        hash.write(&[0]);
+
+        if let Some(memmap) = self.get_tag(STIVALE2_STRUCT_TAG_MEMMAP_ID) {
+            let memmap = memmap as *const Stivale2StructTagMemmap<0>;
+            let entries = unsafe { (*memmap).entries };
+            let first =
+                unsafe { core::ptr::addr_of!((*memmap).memmap) } as *const Stivale2MMapEntry;
+
+            for i in 0..entries as usize {
+                hash.write_entry(unsafe { &*first.add(i) });
+            }
+        }
+
+        hash.finish()
+    }
+
+    /// Get the PXE server info.
+    pub fn get_pxe_server_info<'a>(&self) -> Option<&'a Stivale2StructTagPxeServerInfo> {
+        self._get(STIVALE2_STRUCT_TAG_PXE_SERVER_INFO_ID)
+    }
+
+    /// Check whether the kernel was booted over the network using PXE.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::*;
+    ///
+    /// let pxe = Stivale2StructTagPxeServerInfo {
+    ///     identifier: STIVALE2_STRUCT_TAG_PXE_SERVER_INFO_ID,
+    ///     next: 0,
+    ///     server_ip: u32::from_ne_bytes([192, 168, 1, 1]),
+    /// };
+    /// let mut info = Stivale2Struct {
+    ///     bootloader_brand: [0; 64],
+    ///     bootloader_version: [0; 64],
+    ///     tags: &pxe as *const _ as u64,
+    /// };
+    ///
+    /// assert!(info.is_network_boot());
+    ///
+    /// info.tags = 0;
+    /// assert!(!info.is_network_boot());
+    /// ```
+    pub fn is_network_boot(&self) -> bool {
+        self.get_pxe_server_info().is_some()
+    }
+
     /// Get a tag using id as type T.
     ///
     /// **Warning**: This will definitely result in a crash if passed the wrong type. Please make
@@ -210,106 +483,3735 @@ pub struct Stivale2StructTagFramebuffer {
     pub blue_mask_shift: u8,
 }
 
-pub const STIVALE2_STRUCT_TAG_TERMINAL_ID: u64 = 0xc2b3f4c3233b0974;
+impl Stivale2StructTagFramebuffer {
+    /// Write the raw pixel value `color` at (`x`, `y`). Out of bounds pixels are ignored.
+    fn write_pixel(&self, x: u32, y: u32, color: u32) {
+        if x >= self.framebuffer_width as u32 || y >= self.framebuffer_height as u32 {
+            return;
+        }
 
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagTerminal {
-    pub identifier: u64,
-    pub next: u64,
-    pub flags: u32,
-    pub cols: u16,
-    pub rows: u16,
-    pub term_write: u64,
-}
+        let bytes_per_pixel = (self.framebuffer_bpp as usize).div_ceil(8);
+        let offset = y as usize * self.framebuffer_pitch as usize + x as usize * bytes_per_pixel;
+        let ptr = (self.framebuffer_addr as *mut u8).wrapping_add(offset);
 
-impl Stivale2StructTagTerminal {
-    /// Get a `term` function to print to framebuffer.
-    /// It is provided by stivale2 boot protocol.
-    pub fn get_term_func(&self) -> impl Fn(&str) {
-        let _t = self.term_write as *const ();
+        unsafe { store_pixel(ptr, bytes_per_pixel, color) }
+    }
 
-        let _term_func =
-            unsafe { core::mem::transmute::<*const (), extern "C" fn(*const i8, u64)>(_t) };
+    /// Fill a `width` by `height` rectangle at (`x`, `y`) with the raw pixel value `color`.
+    fn fill_rect(&self, x: u32, y: u32, width: u32, height: u32, color: u32) {
+        for row in y..y + height {
+            for col in x..x + width {
+                self.write_pixel(col, row, color);
+            }
+        }
+    }
 
-        move |txt| {
-            _term_func(txt.as_ptr() as *const i8, txt.len() as u64);
+    /// Draw a `width` by `height` progress bar at (`x`, `y`) with a 1 pixel `border`, filled with
+    /// `fg` from the left in proportion to `percent` and with `bg` for the rest. `percent` is
+    /// clamped to 100. All colors are raw pixel values.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(fb: &stivale_rs::v2::Stivale2StructTagFramebuffer, loaded: u8) {
+    ///     fb.draw_progress_bar(100, 100, 200, 16, loaded, 0x00ff00, 0x202020, 0xffffff);
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_progress_bar(
+        &self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        percent: u8,
+        fg: u32,
+        bg: u32,
+        border: u32,
+    ) {
+        if width < 2 || height < 2 {
+            self.fill_rect(x as u32, y as u32, width as u32, height as u32, border);
+            return;
         }
+
+        let (x, y, width, height) = (x as u32, y as u32, width as u32, height as u32);
+        let percent = core::cmp::min(percent, 100) as u32;
+
+        self.fill_rect(x, y, width, 1, border);
+        self.fill_rect(x, y + height - 1, width, 1, border);
+        self.fill_rect(x, y + 1, 1, height - 2, border);
+        self.fill_rect(x + width - 1, y + 1, 1, height - 2, border);
+
+        let inner_width = width - 2;
+        let filled = inner_width * percent / 100;
+
+        self.fill_rect(x + 1, y + 1, filled, height - 2, fg);
+        self.fill_rect(x + 1 + filled, y + 1, inner_width - filled, height - 2, bg);
     }
-}
 
-/// This tag reports to the kernel the command line string that was passed to it by the bootloader.
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagCmdline {
-    pub identifier: u64,
-    pub next: u64,
-    pub cmdline: u64,
-}
+    /// Copy the visible pixels of the framebuffer row by row into `dst`, leaving out the padding
+    /// at the end of each row, e.g. to dump the screen or to restore it after a mode switch.
+    ///
+    /// Only 32bpp framebuffers are supported. Returns the number of pixels copied, which is 0
+    /// for other depths and is bounded by the length of `dst`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(fb: &stivale_rs::v2::Stivale2StructTagFramebuffer, screenshot: &mut [u32]) {
+    ///     let copied = fb.copy_to(screenshot);
+    ///     // `screenshot[..copied]` holds the screen, row by row.
+    /// }
+    /// ```
+    pub fn copy_to(&self, dst: &mut [u32]) -> usize {
+        if self.framebuffer_bpp != 32 || self.framebuffer_width == 0 {
+            return 0;
+        }
 
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagMemmap<const ENTRIES_LEN: usize> {
-    pub identifier: u64,
-    pub next: u64,
-    pub entries: u64,
-    pub memmap: [Stivale2MMapEntry; ENTRIES_LEN],
-}
+        let width = self.framebuffer_width as usize;
+        let mut copied = 0;
 
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2MMapEntry {
-    pub base: u64,
-    pub length: u64,
-    pub r#type: u32,
-    pub unsed: u32,
-}
+        for (y, row) in dst
+            .chunks_mut(width)
+            .take(self.framebuffer_height as usize)
+            .enumerate()
+        {
+            let src = (self.framebuffer_addr as usize + y * self.framebuffer_pitch as usize)
+                as *const u32;
 
-#[repr(u32)]
-pub enum Stivale2MMapType {
-    Usable = 1,
-    Reserved,
-    ACPIReclaimable,
-    ACPINvs,
-    BadMemory,
-    BootloaderReclaimable = 0x1000,
-    KernelAndModules = 0x1001,
-    Framebuffer = 0x1002,
-}
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = unsafe { core::ptr::read_volatile(src.add(x)) };
+            }
+            copied += row.len();
+        }
 
-/// This tag reports to the kernel the current UNIX epoch, as per RTC.
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagEpoch {
-    pub identifier: u64,
-    pub next: u64,
-    pub epoch: u64,
-}
+        copied
+    }
 
-/// This tag reports to the kernel info about the firmware.
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagFirmware {
-    pub identifier: u64,
-    pub next: u64,
-    pub flags: u64,
-}
+    /// Copy `height` rows of `width` pixels from `src` to (`dst_x`, `dst_y`) using `copy_row`,
+    /// clipped to the framebuffer and to the length of `src`.
+    #[allow(clippy::too_many_arguments)]
+    fn blit_rows(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+        width: u16,
+        height: u16,
+        copy_row: impl Fn(&[u8], *mut u8),
+    ) {
+        let bytes_per_pixel = (self.framebuffer_bpp as usize).div_ceil(8);
+        let width = core::cmp::min(width, self.framebuffer_width.saturating_sub(dst_x));
+        let height = core::cmp::min(height, self.framebuffer_height.saturating_sub(dst_y));
+        let row_len = width as usize * bytes_per_pixel;
 
-/// This tag provides the kernel with a pointer to the EFI system table if available.
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagEFISystemTable {
-    pub identifier: u64,
-    pub next: u64,
-    pub system_table: u64,
-}
+        for row in 0..height as usize {
+            let src_row = row
+                .checked_mul(src_stride)
+                .and_then(|start| Some(start..start.checked_add(row_len)?))
+                .and_then(|range| src.get(range));
+            let src_row = match src_row {
+                Some(src_row) => src_row,
+                None => return,
+            };
 
-/// This tag provides the kernel with a pointer to a copy the raw executable file of the kernel
-/// that the bootloader loaded.
-#[repr(C)]
-#[derive(core::fmt::Debug)]
-pub struct Stivale2StructTagKernelFile {
-    pub identifier: u64,
-    pub next: u64,
-    pub kernel_file: u64,
+            let offset = (dst_y as usize + row) * self.framebuffer_pitch as usize
+                + dst_x as usize * bytes_per_pixel;
+            copy_row(
+                src_row,
+                (self.framebuffer_addr as *mut u8).wrapping_add(offset),
+            );
+        }
+    }
+
+    /// Copy a `width` by `height` rectangle of pixels from `src` to (`dst_x`, `dst_y`). `src` has
+    /// to be in the pixel format of the framebuffer, with rows `src_stride` bytes apart. The
+    /// rectangle is clipped to the framebuffer and to the length of `src`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(fb: &stivale_rs::v2::Stivale2StructTagFramebuffer, logo: &[u8]) {
+    ///     // A 64x64 logo in the pixel format of a 32bpp framebuffer.
+    ///     fb.blit_rect(logo, 64 * 4, 16, 16, 64, 64);
+    /// }
+    /// ```
+    pub fn blit_rect(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+        width: u16,
+        height: u16,
+    ) {
+        self.blit_rows(
+            src,
+            src_stride,
+            dst_x,
+            dst_y,
+            width,
+            height,
+            |src, dst| unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) },
+        );
+    }
+
+    /// Same as [`blit_rect`](Self::blit_rect), but copies 32bpp framebuffers with 256 bit AVX2
+    /// stores. Falls back to `blit_rect` for other depths, and entirely when the `avx2`
+    /// feature is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2. Use [`blit_rect_dispatch`](Self::blit_rect_dispatch) to check
+    /// at runtime.
+    #[cfg(all(target_arch = "x86_64", feature = "avx2"))]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn blit_rect_simd(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+        width: u16,
+        height: u16,
+    ) {
+        use core::arch::x86_64::{__m256i, _mm256_loadu_si256, _mm256_storeu_si256};
+
+        if self.framebuffer_bpp != 32 {
+            return self.blit_rect(src, src_stride, dst_x, dst_y, width, height);
+        }
+
+        self.blit_rows(
+            src,
+            src_stride,
+            dst_x,
+            dst_y,
+            width,
+            height,
+            |src, dst| unsafe {
+                let chunks = src.chunks_exact(32);
+                let remainder = chunks.remainder();
+
+                for (i, chunk) in chunks.enumerate() {
+                    let value = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+                    _mm256_storeu_si256(dst.add(i * 32) as *mut __m256i, value);
+                }
+
+                let copied = src.len() - remainder.len();
+                core::ptr::copy_nonoverlapping(
+                    remainder.as_ptr(),
+                    dst.add(copied),
+                    remainder.len(),
+                );
+            },
+        );
+    }
+
+    /// Same as [`blit_rect`](Self::blit_rect), but copies 32bpp framebuffers with 256 bit AVX2
+    /// stores. Falls back to `blit_rect` for other depths, and entirely when the `avx2`
+    /// feature is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2. Use [`blit_rect_dispatch`](Self::blit_rect_dispatch) to check
+    /// at runtime.
+    #[cfg(not(all(target_arch = "x86_64", feature = "avx2")))]
+    pub unsafe fn blit_rect_simd(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+        width: u16,
+        height: u16,
+    ) {
+        self.blit_rect(src, src_stride, dst_x, dst_y, width, height);
+    }
+
+    /// Copy a rectangle of pixels like [`blit_rect`](Self::blit_rect), using
+    /// [`blit_rect_simd`](Self::blit_rect_simd) if the `avx2` feature is enabled and CPUID
+    /// reports that the CPU and OS support AVX2.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(fb: &stivale_rs::v2::Stivale2StructTagFramebuffer, logo: &[u8]) {
+    ///     fb.blit_rect_dispatch(logo, 64 * 4, 16, 16, 64, 64);
+    /// }
+    /// ```
+    pub fn blit_rect_dispatch(
+        &self,
+        src: &[u8],
+        src_stride: usize,
+        dst_x: u16,
+        dst_y: u16,
+        width: u16,
+        height: u16,
+    ) {
+        #[cfg(all(target_arch = "x86_64", feature = "avx2"))]
+        if has_avx2() {
+            return unsafe { self.blit_rect_simd(src, src_stride, dst_x, dst_y, width, height) };
+        }
+
+        self.blit_rect(src, src_stride, dst_x, dst_y, width, height);
+    }
+
+    /// Create a [`PixelWriter`] with the pixel format of the framebuffer precomputed.
+    pub fn pixel_writer(&self) -> PixelWriter<'_> {
+        let channel = |size: u8, shift: u8| {
+            if size >= 8 {
+                (0, shift as u32 + (size as u32 - 8))
+            } else {
+                (8 - size as u32, shift as u32)
+            }
+        };
+
+        PixelWriter {
+            fb: self,
+            addr: self.framebuffer_addr as *mut u8,
+            bytes_per_pixel: (self.framebuffer_bpp as usize).div_ceil(8),
+            pitch: self.framebuffer_pitch as usize,
+            masks: [
+                channel(self.red_mask_size, self.red_mask_shift),
+                channel(self.green_mask_size, self.green_mask_shift),
+                channel(self.blue_mask_size, self.blue_mask_shift),
+            ],
+        }
+    }
+
+    /// Get the size of the framebuffer in bytes.
+    pub fn size(&self) -> u64 {
+        self.framebuffer_pitch as u64 * self.framebuffer_height as u64
+    }
+
+    /// Pack an RGB color into a raw pixel value using the color masks of the framebuffer.
+    pub fn rgb(&self, r: u8, g: u8, b: u8) -> u32 {
+        let component = |value: u8, size: u8, shift: u8| {
+            let value = if size >= 8 {
+                (value as u32) << (size - 8)
+            } else {
+                (value as u32) >> (8 - size)
+            };
+            value << shift
+        };
+
+        component(r, self.red_mask_size, self.red_mask_shift)
+            | component(g, self.green_mask_size, self.green_mask_shift)
+            | component(b, self.blue_mask_size, self.blue_mask_shift)
+    }
+
+    /// Plot an RGB pixel at (`x`, `y`). Out of bounds pixels are ignored.
+    pub fn put_pixel(&self, x: u16, y: u16, r: u8, g: u8, b: u8) {
+        self.write_pixel(x as u32, y as u32, self.rgb(r, g, b));
+    }
+
+    /// Fill the visible part of the framebuffer with the raw pixel value `color`.
+    ///
+    /// 32bpp framebuffers with 8 byte aligned rows are cleared two pixels at a time with `u64`
+    /// stores, other 32bpp framebuffers with `u32` stores.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(fb: &stivale_rs::v2::Stivale2StructTagFramebuffer) {
+    ///     fb.clear(0x000000);
+    /// }
+    /// ```
+    pub fn clear(&self, color: u32) {
+        let width = self.framebuffer_width as usize;
+        let pitch = self.framebuffer_pitch as usize;
+
+        if self.framebuffer_bpp != 32 {
+            for y in 0..self.framebuffer_height as u32 {
+                for x in 0..width as u32 {
+                    self.write_pixel(x, y, color);
+                }
+            }
+            return;
+        }
+
+        let wide = self.framebuffer_addr.is_multiple_of(8) && pitch.is_multiple_of(8);
+        let pair = (color as u64) << 32 | color as u64;
+
+        for y in 0..self.framebuffer_height as usize {
+            let row = (self.framebuffer_addr as usize + y * pitch) as *mut u32;
+
+            unsafe {
+                if wide {
+                    for i in 0..width / 2 {
+                        core::ptr::write_volatile((row as *mut u64).add(i), pair);
+                    }
+                    if !width.is_multiple_of(2) {
+                        core::ptr::write_volatile(row.add(width - 1), color);
+                    }
+                } else {
+                    for x in 0..width {
+                        core::ptr::write_volatile(row.add(x), color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw the `w` by `h` frame of palette indices `indexed_frame` at (`x`, `y`), looking every
+    /// index up in `palette`. Pixels with the index `skip_index` are left untouched, so it acts as
+    /// the transparent color.
+    ///
+    /// Redrawing a frame after [`animate_palette`] animates it without re-encoding the frame.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stivale_rs::v2::{animate_palette, Stivale2Palette, Stivale2StructTagFramebuffer};
+    ///
+    /// fn spin(fb: &Stivale2StructTagFramebuffer, frame: &[u8], palette: &mut Stivale2Palette<256>) {
+    ///     // A 32x32 spinner with index 0 as the transparent color.
+    ///     loop {
+    ///         fb.draw_palette_swap(frame, 32, 32, 100, 100, palette, 0);
+    ///         animate_palette(palette, 1);
+    ///     }
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_palette_swap(
+        &self,
+        indexed_frame: &[u8],
+        w: u16,
+        h: u16,
+        x: u16,
+        y: u16,
+        palette: &Stivale2Palette<256>,
+        skip_index: u8,
+    ) {
+        if w == 0 {
+            return;
+        }
+
+        let rows = indexed_frame.chunks(w as usize).take(h as usize);
+
+        for (row, indices) in rows.enumerate() {
+            for (col, &index) in indices.iter().enumerate() {
+                if index == skip_index {
+                    continue;
+                }
+
+                let color = palette.colors[index as usize];
+                self.write_pixel(x as u32 + col as u32, y as u32 + row as u32, color);
+            }
+        }
+    }
+
+    /// Draw a line from (`x0`, `y0`) to (`x1`, `y1`) with the raw pixel value `color` using
+    /// Bresenham's line algorithm. Only integer arithmetic is used. Points outside of the
+    /// framebuffer are clipped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(info: &stivale_rs::v2::Stivale2Struct) {
+    ///     if let Some(fb) = info.get_framebuffer() {
+    ///         let (w, h) = (fb.framebuffer_width as i32, fb.framebuffer_height as i32);
+    ///         // Cross out the screen.
+    ///         fb.draw_line(0, 0, w - 1, h - 1, 0xffffff);
+    ///         fb.draw_line(w - 1, 0, 0, h - 1, 0xffffff);
+    ///     }
+    /// }
+    /// ```
+    pub fn draw_line(&self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        let (dx, dy) = (x1 as i64 - x0 as i64, y1 as i64 - y0 as i64);
+        let (width, height) = (
+            self.framebuffer_width as i64,
+            self.framebuffer_height as i64,
+        );
+
+        // The line is stepped along its major axis. At step `t`, the major coordinate moves by
+        // `t` and the minor coordinate by `q(t)`, `t * minor_len / major_len` rounded half up.
+        let x_major = dx.abs() >= dy.abs();
+        let (major0, minor0, major_d, minor_d, major_size, minor_size) = if x_major {
+            (x0 as i64, y0 as i64, dx, dy, width, height)
+        } else {
+            (y0 as i64, x0 as i64, dy, dx, height, width)
+        };
+        let (major_step, minor_step) = (major_d.signum(), minor_d.signum());
+        let (major_len, minor_len) = (major_d.abs() as i128, minor_d.abs() as i128);
+
+        let q = |t: i128| {
+            if major_len == 0 {
+                0
+            } else {
+                (2 * minor_len * t + major_len) / (2 * major_len)
+            }
+        };
+        let div_ceil = |a: i128, b: i128| -((-a).div_euclid(b));
+
+        // Clip to the steps that are inside of the framebuffer on both axes before stepping.
+        let (major_lo, major_hi) = match clip_axis(major0, major_step, major_size) {
+            Some(range) => range,
+            None => return,
+        };
+        let (minor_lo, minor_hi) = match clip_axis(minor0, minor_step, minor_size) {
+            Some(range) => range,
+            None => return,
+        };
+
+        let (mut t_lo, mut t_hi) = (
+            core::cmp::max(0, major_lo),
+            core::cmp::min(major_len, major_hi),
+        );
+        if minor_len != 0 {
+            // q(t) >= k for t >= ceil((2k - 1) * major_len / (2 * minor_len)), and q(t) <= k
+            // for t < ceil((2k + 1) * major_len / (2 * minor_len)).
+            let first = div_ceil((2 * minor_lo - 1) * major_len, 2 * minor_len);
+            let last = div_ceil((2 * minor_hi + 1) * major_len, 2 * minor_len) - 1;
+            t_lo = core::cmp::max(t_lo, first);
+            t_hi = core::cmp::min(t_hi, last);
+        }
+
+        if t_lo > t_hi {
+            return;
+        }
+
+        let mut minor = q(t_lo);
+        let mut err = 2 * minor_len * (t_lo + 1) - major_len - 2 * major_len * minor;
+
+        for t in t_lo..=t_hi {
+            let major = (major0 as i128 + major_step as i128 * t) as u32;
+            let minor_pos = (minor0 as i128 + minor_step as i128 * minor) as u32;
+            let (x, y) = if x_major {
+                (major, minor_pos)
+            } else {
+                (minor_pos, major)
+            };
+            self.write_pixel(x, y, color);
+
+            if err >= 0 {
+                minor += 1;
+                err -= 2 * major_len;
+            }
+            err += 2 * minor_len;
+        }
+    }
+}
+
+/// A table of raw pixel values indexed by color index.
+#[derive(Clone, Copy, core::fmt::Debug)]
+pub struct Stivale2Palette<const N: usize> {
+    pub colors: [u32; N],
+}
+
+/// Rotate the colors of `palette` forward by `shift` entries, e.g. once per animation tick.
+/// Color 0 is kept in place as it is usually the transparent color.
+pub fn animate_palette(palette: &mut Stivale2Palette<256>, shift: usize) {
+    let colors = &mut palette.colors[1..];
+    let len = colors.len();
+
+    colors.rotate_right(shift % len);
+}
+
+/// Store the raw pixel value `color` at `ptr`, writing only `bytes_per_pixel` bytes.
+unsafe fn store_pixel(ptr: *mut u8, bytes_per_pixel: usize, color: u32) {
+    if bytes_per_pixel == 4 {
+        core::ptr::write_volatile(ptr as *mut u32, color);
+    } else {
+        for (i, byte) in color.to_le_bytes().iter().take(bytes_per_pixel).enumerate() {
+            core::ptr::write_volatile(ptr.add(i), *byte);
+        }
+    }
+}
+
+/// Check whether the CPU supports AVX2 and the OS enabled saving the AVX register state.
+#[cfg(all(target_arch = "x86_64", feature = "avx2"))]
+fn has_avx2() -> bool {
+    use core::arch::x86_64::{__cpuid, __cpuid_count, _xgetbv};
+
+    const OSXSAVE: u32 = 1 << 27;
+    const AVX: u32 = 1 << 28;
+    const XCR0_SSE_AVX: u64 = 0b110;
+    const AVX2: u32 = 1 << 5;
+
+    if __cpuid(0).eax < 7 {
+        return false;
+    }
+
+    let features = __cpuid(1).ecx;
+    if features & OSXSAVE == 0 || features & AVX == 0 {
+        return false;
+    }
+
+    if unsafe { _xgetbv(0) } & XCR0_SSE_AVX != XCR0_SSE_AVX {
+        return false;
+    }
+
+    __cpuid_count(7, 0).ebx & AVX2 != 0
+}
+
+/// Writes RGB pixels to a framebuffer with its pixel format precomputed, for use in tight
+/// drawing loops where [`Stivale2StructTagFramebuffer::put_pixel`] would recompute it for every
+/// pixel.
+///
+/// # Example
+///
+/// ```no_run
+/// fn entry(info: &stivale_rs::v2::Stivale2Struct) {
+///     if let Some(fb) = info.get_framebuffer() {
+///         let writer = fb.pixel_writer();
+///
+///         // Fill the screen with a gradient.
+///         for y in 0..fb.framebuffer_height {
+///             for x in 0..fb.framebuffer_width {
+///                 writer.put(x, y, x as u8, y as u8, 0x80);
+///             }
+///         }
+///     }
+/// }
+/// ```
+pub struct PixelWriter<'a> {
+    fb: &'a Stivale2StructTagFramebuffer,
+    addr: *mut u8,
+    bytes_per_pixel: usize,
+    pitch: usize,
+    /// The right shift that scales an 8 bit component down to the mask size and the left shift
+    /// that moves it into place, for red, green and blue.
+    masks: [(u32, u32); 3],
+}
+
+impl PixelWriter<'_> {
+    /// Plot an RGB pixel at (`x`, `y`). Out of bounds pixels are ignored.
+    #[inline]
+    pub fn put(&self, x: u16, y: u16, r: u8, g: u8, b: u8) {
+        if x >= self.fb.framebuffer_width || y >= self.fb.framebuffer_height {
+            return;
+        }
+
+        let [(r_down, r_up), (g_down, g_up), (b_down, b_up)] = self.masks;
+        let color = ((r as u32 >> r_down) << r_up)
+            | ((g as u32 >> g_down) << g_up)
+            | ((b as u32 >> b_down) << b_up);
+
+        let offset = y as usize * self.pitch + x as usize * self.bytes_per_pixel;
+        unsafe { store_pixel(self.addr.wrapping_add(offset), self.bytes_per_pixel, color) }
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_TERMINAL_ID: u64 = 0xc2b3f4c3233b0974;
+
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagTerminal {
+    pub identifier: u64,
+    pub next: u64,
+    pub flags: u32,
+    pub cols: u16,
+    pub rows: u16,
+    pub term_write: u64,
+}
+
+impl Stivale2StructTagTerminal {
+    /// Get a `term` function to print to framebuffer.
+    /// It is provided by stivale2 boot protocol.
+    pub fn get_term_func(&self) -> impl Fn(&str) {
+        let _t = self.term_write as *const ();
+
+        let _term_func =
+            unsafe { core::mem::transmute::<*const (), extern "C" fn(*const i8, u64)>(_t) };
+
+        move |txt| {
+            _term_func(txt.as_ptr() as *const i8, txt.len() as u64);
+        }
+    }
+
+    /// Get a [`TerminalWriter`] for the terminal, or `None` if the bootloader didn't provide a
+    /// usable terminal.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use core::fmt::Write;
+    /// use std::sync::Mutex;
+    ///
+    /// static OUTPUT: Mutex<String> = Mutex::new(String::new());
+    ///
+    /// extern "C" fn term_write(ptr: *const i8, len: u64) {
+    ///     let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, len as usize) };
+    ///     OUTPUT.lock().unwrap().push_str(std::str::from_utf8(bytes).unwrap());
+    /// }
+    ///
+    /// let terminal = stivale_rs::v2::Stivale2StructTagTerminal {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     flags: 0,
+    ///     cols: 80,
+    ///     rows: 25,
+    ///     term_write: term_write as usize as u64,
+    /// };
+    ///
+    /// let mut writer = terminal.writer().unwrap();
+    /// write!(writer, "booted in {}ms", 42).unwrap();
+    ///
+    /// assert_eq!(*OUTPUT.lock().unwrap(), "booted in 42ms");
+    /// ```
+    pub fn writer(&self) -> Option<TerminalWriter> {
+        if self.term_write == 0 || self.cols == 0 || self.rows == 0 {
+            return None;
+        }
+
+        let term_write = unsafe {
+            core::mem::transmute::<*const (), extern "C" fn(*const i8, u64)>(
+                self.term_write as *const (),
+            )
+        };
+
+        Some(TerminalWriter { term_write })
+    }
+}
+
+/// Writes to the terminal provided by the bootloader through `core::fmt::Write`. Created by
+/// [`Stivale2StructTagTerminal::writer`].
+pub struct TerminalWriter {
+    term_write: extern "C" fn(*const i8, u64),
+}
+
+impl core::fmt::Write for TerminalWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        (self.term_write)(s.as_ptr() as *const i8, s.len() as u64);
+        Ok(())
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_CMDLINE_ID: u64 = 0xe5e76a1b4597a781;
+
+/// This tag reports to the kernel the command line string that was passed to it by the bootloader.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagCmdline {
+    pub identifier: u64,
+    pub next: u64,
+    pub cmdline: u64,
+}
+
+impl Stivale2StructTagCmdline {
+    /// Get a pointer to the command line that stays valid after switching to page tables that
+    /// only map physical memory through the higher half direct map at `hhdm_offset`.
+    ///
+    /// Pass 0 as `hhdm_offset` while physical memory is still identity mapped, in which case the
+    /// pointer is returned unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let cmdline = stivale_rs::v2::Stivale2StructTagCmdline {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     cmdline: 0x1000,
+    /// };
+    ///
+    /// assert_eq!(cmdline.cmdline_ptr(0xffff800000000000) as u64, 0xffff800000001000);
+    /// assert_eq!(cmdline.cmdline_ptr(0) as u64, 0x1000);
+    /// ```
+    pub fn cmdline_ptr(&self, hhdm_offset: u64) -> *const u8 {
+        self.cmdline.wrapping_add(hhdm_offset) as *const u8
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_MEMMAP_ID: u64 = 0x2187f79e8612de07;
+
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagMemmap<const ENTRIES_LEN: usize> {
+    pub identifier: u64,
+    pub next: u64,
+    pub entries: u64,
+    pub memmap: [Stivale2MMapEntry; ENTRIES_LEN],
+}
+
+impl<const ENTRIES_LEN: usize> Stivale2StructTagMemmap<ENTRIES_LEN> {
+    /// Get the entries reported by the bootloader as a slice.
+    ///
+    /// The slice is bounded by both `entries` and `ENTRIES_LEN`.
+    pub fn as_slice(&self) -> &[Stivale2MMapEntry] {
+        let len = core::cmp::min(self.entries as usize, ENTRIES_LEN);
+        &self.memmap[..len]
+    }
+
+    /// Get the entry containing the physical address `phys`.
+    pub fn find(&self, phys: u64) -> Option<&Stivale2MMapEntry> {
+        self.index_of(phys).map(|idx| &self.memmap[idx])
+    }
+
+    /// Get the index of the entry containing the physical address `phys`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>, phys: u64) {
+    ///     let idx = memmap.index_of(phys).expect("address not in the memory map");
+    ///     // `memmap.memmap[idx]` is the entry backing `phys`.
+    /// }
+    /// ```
+    pub fn index_of(&self, phys: u64) -> Option<usize> {
+        self.as_slice().iter().position(|e| e.contains(phys))
+    }
+
+    /// Check whether the whole range `[base, base + len)` is covered by usable entries.
+    ///
+    /// The range may span several adjacent usable entries.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>) {
+    ///     // Make sure the 16MiB the kernel wants for its heap are free.
+    ///     assert!(memmap.is_range_usable(0x1000000, 0x1000000));
+    /// }
+    /// ```
+    pub fn is_range_usable(&self, base: u64, len: u64) -> bool {
+        self.is_range_covered(base, len, Stivale2MMapEntry::is_usable)
+    }
+
+    /// Check whether the base and length of every usable entry are aligned to `page_size`.
+    ///
+    /// The stivale2 specification guarantees this, so a false result means the bootloader is
+    /// misbehaving and the memory map can't be trusted by the allocator.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>) {
+    ///     assert!(memmap.usable_regions_aligned(0x1000), "the bootloader broke its promises");
+    /// }
+    /// ```
+    pub fn usable_regions_aligned(&self, page_size: u64) -> bool {
+        if page_size == 0 {
+            return false;
+        }
+
+        self.as_slice()
+            .iter()
+            .filter(|e| e.is_usable())
+            .all(|e| e.base % page_size == 0 && e.length % page_size == 0)
+    }
+
+    /// Iterate over the usable memory as `(start, end)` ranges with the region
+    /// `[exclude_base, exclude_base + exclude_len)` removed, splitting entries where needed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>, heap_start: u64, heap_len: u64) {
+    ///     for (start, end) in memmap.usable_excluding(heap_start, heap_len) {
+    ///         // Hand `start..end` to the page frame allocator.
+    ///     }
+    /// }
+    /// ```
+    pub fn usable_excluding(
+        &self,
+        exclude_base: u64,
+        exclude_len: u64,
+    ) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let (exclude_start, exclude_end) = if exclude_len == 0 {
+            (u64::MAX, u64::MAX)
+        } else {
+            (exclude_base, exclude_base.saturating_add(exclude_len))
+        };
+
+        self.as_slice()
+            .iter()
+            .filter(|e| e.is_usable())
+            .flat_map(move |e| {
+                let (start, end) = (e.base, e.base.saturating_add(e.length));
+                let below = (start, core::cmp::min(end, exclude_start));
+                let above = (core::cmp::max(start, exclude_end), end);
+
+                core::iter::once(below)
+                    .chain(core::iter::once(above))
+                    .filter(|(start, end)| start < end)
+            })
+    }
+
+    /// Iterate over the usable memory below `limit` as `(start, end)` ranges, e.g. for devices
+    /// that can only DMA below 4GiB. Entries crossing `limit` are cut off at `limit`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>) {
+    ///     for (start, end) in memmap.usable_below(0x1_0000_0000) {
+    ///         // Hand `start..end` to the 32 bit DMA allocator.
+    ///     }
+    /// }
+    /// ```
+    pub fn usable_below(&self, limit: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.as_slice()
+            .iter()
+            .filter(move |e| e.is_usable() && e.base < limit)
+            .map(move |e| {
+                let end = core::cmp::min(e.base.saturating_add(e.length), limit);
+                (e.base, end)
+            })
+            .filter(|(start, end)| start < end)
+    }
+
+    /// Compute a 64 bit FNV-1a hash of the base, length and type of every entry, in order. The
+    /// hash is stable across boots with the same memory layout, so kernels can use it to detect
+    /// layout changes, e.g. before resuming from hibernation.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>, saved_hash: u64) {
+    ///     if memmap.layout_hash() != saved_hash {
+    ///         // The memory layout changed since hibernation, boot normally instead of resuming.
+    ///     }
+    /// }
+    /// ```
+    pub fn layout_hash(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+
+        for entry in self.as_slice() {
+            hash.write_entry(entry);
+        }
+
+        hash.finish()
+    }
+
+    /// Iterate over the ACPI NVS entries, which have to be preserved across S3 sleep.
+    pub fn nvs_regions<'a>(&'a self) -> impl Iterator<Item = &'a Stivale2MMapEntry> + 'a {
+        self.as_slice()
+            .iter()
+            .filter(|e| e.r#type == Stivale2MMapType::ACPINvs as u32)
+    }
+
+    /// Get the total size of the ACPI NVS entries in bytes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>) {
+    ///     // Reserve room to save the ACPI NVS regions to before entering S3.
+    ///     let save_area_size = memmap.total_nvs_size();
+    /// }
+    /// ```
+    pub fn total_nvs_size(&self) -> u64 {
+        self.nvs_regions().map(|e| e.length).sum()
+    }
+
+    /// Check whether the whole range `[base, base + len)` is covered by entries matching `pred`.
+    fn is_range_covered(
+        &self,
+        base: u64,
+        len: u64,
+        pred: impl Fn(&Stivale2MMapEntry) -> bool,
+    ) -> bool {
+        let end = match base.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        let mut current = base;
+
+        while current < end {
+            let entry = self
+                .as_slice()
+                .iter()
+                .find(|e| pred(e) && e.contains(current));
+
+            match entry {
+                Some(e) => current = e.base.saturating_add(e.length),
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Find a page aligned usable region of `size` bytes above 1MiB to be used as an early heap.
+    ///
+    /// Returns the `(start, end)` of the region. Only usable entries are considered, so the region
+    /// never overlaps the kernel or modules.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// fn entry(memmap: &stivale_rs::v2::Stivale2StructTagMemmap<64>) {
+    ///     // A 1MiB heap made of 4KiB pages.
+    ///     let (start, end) = memmap.carve_heap(0x100000, 0x1000).expect("no room for the heap");
+    ///     // Hand `start..end` to the allocator.
+    /// }
+    /// ```
+    pub fn carve_heap(&self, size: u64, page_size: u64) -> Option<(u64, u64)> {
+        const MIN_BASE: u64 = 0x100000;
+
+        self.as_slice()
+            .iter()
+            .filter(|e| e.is_usable())
+            .find_map(|e| {
+                let start = align_up(core::cmp::max(e.base, MIN_BASE), page_size)?;
+                let end = start.checked_add(size)?;
+
+                if end <= e.base.saturating_add(e.length) {
+                    Some((start, end))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Mark the range `[base, base + length)` as `r#type`, splitting usable entries that only
+    /// partially overlap it. Entries that are not usable are left untouched.
+    ///
+    /// Returns false without modifying the map if `memmap` doesn't have enough spare capacity
+    /// for the split entries, or if the bootloader reported more entries than `ENTRIES_LEN`, as
+    /// writing the map back would drop the entries that don't fit.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stivale_rs::v2::{Stivale2MMapType, Stivale2StructTagMemmap};
+    ///
+    /// fn entry(memmap: &mut Stivale2StructTagMemmap<64>) {
+    ///     // Keep the allocator away from a buffer handed to the firmware.
+    ///     assert!(memmap.reserve_range(0x1000000, 0x10000, Stivale2MMapType::Reserved));
+    /// }
+    /// ```
+    pub fn reserve_range(&mut self, base: u64, length: u64, r#type: Stivale2MMapType) -> bool {
+        if self.entries > ENTRIES_LEN as u64 {
+            return false;
+        }
+
+        let end = match base.checked_add(length) {
+            Some(end) if end > base => end,
+            Some(_) => return true,
+            None => return false,
+        };
+        let overlaps = |e: &Stivale2MMapEntry| {
+            e.is_usable() && e.base < end && base < e.base.saturating_add(e.length)
+        };
+
+        let extra: usize = self
+            .as_slice()
+            .iter()
+            .filter(|e| overlaps(e))
+            .map(|e| (e.base < base) as usize + (e.base.saturating_add(e.length) > end) as usize)
+            .sum();
+
+        let mut len = self.entries as usize;
+        if len + extra > ENTRIES_LEN {
+            return false;
+        }
+
+        let mut i = 0;
+        while i < len {
+            let entry = self.memmap[i];
+            if !overlaps(&entry) {
+                i += 1;
+                continue;
+            }
+
+            let entry_end = entry.base.saturating_add(entry.length);
+            let start = core::cmp::max(entry.base, base);
+            let stop = core::cmp::min(entry_end, end);
+
+            let piece = |base: u64, end: u64, r#type: u32| Stivale2MMapEntry {
+                base,
+                length: end - base,
+                r#type,
+                unsed: entry.unsed,
+            };
+            let mut pieces = [entry; 3];
+            let mut count = 0;
+
+            if entry.base < start {
+                pieces[count] = piece(entry.base, start, entry.r#type);
+                count += 1;
+            }
+            pieces[count] = piece(start, stop, r#type as u32);
+            count += 1;
+            if stop < entry_end {
+                pieces[count] = piece(stop, entry_end, entry.r#type);
+                count += 1;
+            }
+
+            self.memmap.copy_within(i + 1..len, i + count);
+            self.memmap[i..i + count].copy_from_slice(&pieces[..count]);
+
+            len += count - 1;
+            i += count;
+        }
+
+        self.entries += extra as u64;
+        true
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, core::fmt::Debug)]
+pub struct Stivale2MMapEntry {
+    pub base: u64,
+    pub length: u64,
+    pub r#type: u32,
+    pub unsed: u32,
+}
+
+impl Stivale2MMapEntry {
+    /// Check whether the entry is usable memory.
+    pub fn is_usable(&self) -> bool {
+        self.r#type == Stivale2MMapType::Usable as u32
+    }
+
+    /// Check whether the entry contains the physical address `phys`.
+    pub fn contains(&self, phys: u64) -> bool {
+        self.base <= phys && phys - self.base < self.length
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+pub enum Stivale2MMapType {
+    Usable = 1,
+    Reserved,
+    ACPIReclaimable,
+    ACPINvs,
+    BadMemory,
+    BootloaderReclaimable = 0x1000,
+    KernelAndModules = 0x1001,
+    Framebuffer = 0x1002,
+}
+
+/// This tag reports to the kernel the current UNIX epoch, as per RTC.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagEpoch {
+    pub identifier: u64,
+    pub next: u64,
+    pub epoch: u64,
+}
+
+pub const STIVALE2_STRUCT_TAG_FIRMWARE_ID: u64 = 0x359d837855e3858c;
+
+/// This tag reports to the kernel info about the firmware.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagFirmware {
+    pub identifier: u64,
+    pub next: u64,
+    pub flags: u64,
+}
+
+impl Stivale2StructTagFirmware {
+    /// Check whether the kernel was booted by UEFI firmware.
+    pub fn is_uefi(&self) -> bool {
+        self.flags & 1 == 0
+    }
+
+    /// Get the kind of firmware the kernel was booted by.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{FirmwareKind, Stivale2StructTagFirmware};
+    ///
+    /// let firmware = |flags| Stivale2StructTagFirmware {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     flags,
+    /// };
+    ///
+    /// assert_eq!(firmware(1).kind(), FirmwareKind::Bios);
+    /// assert_eq!(firmware(0).kind(), FirmwareKind::Uefi);
+    /// ```
+    pub fn kind(&self) -> FirmwareKind {
+        if self.is_uefi() {
+            FirmwareKind::Uefi
+        } else {
+            FirmwareKind::Bios
+        }
+    }
+}
+
+/// The kind of firmware that booted the kernel.
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+#[non_exhaustive]
+pub enum FirmwareKind {
+    Bios,
+    Uefi,
+}
+
+/// This tag provides the kernel with a pointer to the EFI system table if available.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagEFISystemTable {
+    pub identifier: u64,
+    pub next: u64,
+    pub system_table: u64,
+}
+
+/// This tag provides the kernel with a pointer to a copy the raw executable file of the kernel
+/// that the bootloader loaded.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagKernelFile {
+    pub identifier: u64,
+    pub next: u64,
+    pub kernel_file: u64,
+}
+
+pub const STIVALE2_STRUCT_TAG_KERNEL_FILE_V2_ID: u64 = 0x37c13018a02c6ea2;
+
+/// This tag provides the kernel with a pointer to a copy the raw executable file of the kernel
+/// that the bootloader loaded, along with its size.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagKernelFileV2 {
+    pub identifier: u64,
+    pub next: u64,
+    pub kernel_file: u64,
+    pub kernel_size: u64,
+}
+
+impl Stivale2StructTagKernelFileV2 {
+    /// Get the number of `page_size` pages the kernel file spans, or 0 if `page_size` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let kernel_file = |kernel_size| stivale_rs::v2::Stivale2StructTagKernelFileV2 {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     kernel_file: 0,
+    ///     kernel_size,
+    /// };
+    ///
+    /// assert_eq!(kernel_file(0x3000).page_count(0x1000), 3);
+    /// assert_eq!(kernel_file(0x3001).page_count(0x1000), 4);
+    /// assert_eq!(kernel_file(0x3001).page_count(0), 0);
+    /// ```
+    pub fn page_count(&self, page_size: u64) -> u64 {
+        if page_size == 0 {
+            return 0;
+        }
+
+        self.kernel_size.div_ceil(page_size)
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_MODULES_ID: u64 = 0x4b6fe466aade04ce;
+
+/// This tag provides the kernel with a list of modules loaded alongside the kernel.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagModules<const MODULES_LEN: usize> {
+    pub identifier: u64,
+    pub next: u64,
+    pub module_count: u64,
+    pub modules: [Stivale2Module; MODULES_LEN],
+}
+
+impl<const MODULES_LEN: usize> Stivale2StructTagModules<MODULES_LEN> {
+    /// Get the modules reported by the bootloader as a slice.
+    ///
+    /// The slice is bounded by both `module_count` and `MODULES_LEN`.
+    pub fn as_slice(&self) -> &[Stivale2Module] {
+        let len = core::cmp::min(self.module_count as usize, MODULES_LEN);
+        &self.modules[..len]
+    }
+
+    /// Get the lowest `begin` and the highest `end` across all modules, or `None` if there are
+    /// no modules.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Module, Stivale2StructTagModules};
+    ///
+    /// let module = |begin, end| Stivale2Module { begin, end, string: [0; 128] };
+    ///
+    /// let modules = Stivale2StructTagModules {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     module_count: 3,
+    ///     modules: [module(0x300000, 0x301000), module(0x100000, 0x180000), module(0x500000, 0x500800)],
+    /// };
+    /// assert_eq!(modules.span(), Some((0x100000, 0x500800)));
+    ///
+    /// let empty = Stivale2StructTagModules { identifier: 0, next: 0, module_count: 0, modules: [] };
+    /// assert_eq!(empty.span(), None);
+    /// ```
+    pub fn span(&self) -> Option<(u64, u64)> {
+        let begin = self.as_slice().iter().map(|m| m.begin).min()?;
+        let end = self.as_slice().iter().map(|m| m.end).max()?;
+
+        Some((begin, end))
+    }
+}
+
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2Module {
+    pub begin: u64,
+    pub end: u64,
+    pub string: [u8; 128],
+}
+
+impl Stivale2Module {
+    /// Get the module string passed to the bootloader, up to the first NUL byte. Returns an
+    /// empty string if it isn't valid UTF-8.
+    pub fn name(&self) -> &str {
+        let len = self
+            .string
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.string.len());
+
+        core::str::from_utf8(&self.string[..len]).unwrap_or("")
+    }
+
+    /// Get the part of the module string after the last `/`, ignoring trailing slashes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let module = |name: &str| {
+    ///     let mut string = [0; 128];
+    ///     string[..name.len()].copy_from_slice(name.as_bytes());
+    ///     stivale_rs::v2::Stivale2Module { begin: 0, end: 0, string }
+    /// };
+    ///
+    /// assert_eq!(module("/boot/initrd.tar").basename(), "initrd.tar");
+    /// assert_eq!(module("boot/fonts/").basename(), "fonts");
+    /// assert_eq!(module("initrd.tar").basename(), "initrd.tar");
+    /// ```
+    pub fn basename(&self) -> &str {
+        self.name()
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or("")
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_KERNEL_BASE_ADDRESS_ID: u64 = 0x060d78874a2a8af0;
+
+/// This tag reports the physical and virtual addresses the kernel was loaded at.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagKernelBaseAddress {
+    pub identifier: u64,
+    pub next: u64,
+    pub physical_base_address: u64,
+    pub virtual_base_address: u64,
+}
+
+pub const STIVALE2_STRUCT_TAG_SMP_ID: u64 = 0x34d1d96339647025;
+
+/// This tag reports to the kernel info about the application processors. It is only present if
+/// the kernel requested SMP in its header.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagSmp<const CPU_COUNT: usize> {
+    pub identifier: u64,
+    pub next: u64,
+    /// Bit 0 is set if x2APIC was requested and is supported.
+    pub flags: u64,
+    pub bsp_lapic_id: u32,
+    pub unused: u32,
+    pub cpu_count: u64,
+    pub smp_info: [Stivale2SmpInfo; CPU_COUNT],
+}
+
+impl<const CPU_COUNT: usize> Stivale2StructTagSmp<CPU_COUNT> {
+    /// Get the per CPU info reported by the bootloader as a slice.
+    ///
+    /// The slice is bounded by both `cpu_count` and `CPU_COUNT`.
+    pub fn as_slice(&self) -> &[Stivale2SmpInfo] {
+        let len = core::cmp::min(self.cpu_count as usize, CPU_COUNT);
+        &self.smp_info[..len]
+    }
+
+    /// Check whether the CPU at `idx` is online.
+    ///
+    /// The stivale2 specification doesn't have a way to report offline CPUs yet, so every CPU
+    /// reported by the bootloader is online. This exists so kernels don't need to change once
+    /// the specification adds one. Returns false if there is no CPU at `idx`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use core::sync::atomic::AtomicU64;
+    /// use stivale_rs::v2::{Stivale2SmpInfo, Stivale2StructTagSmp};
+    ///
+    /// let smp = Stivale2StructTagSmp {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     flags: 0,
+    ///     bsp_lapic_id: 0,
+    ///     unused: 0,
+    ///     cpu_count: 1,
+    ///     smp_info: [Stivale2SmpInfo {
+    ///         processor_id: 0,
+    ///         lapic_id: 0,
+    ///         target_stack: 0,
+    ///         goto_address: AtomicU64::new(0),
+    ///         extra_argument: AtomicU64::new(0),
+    ///     }],
+    /// };
+    ///
+    /// assert!(smp.is_cpu_online(0));
+    /// assert!(!smp.is_cpu_online(1));
+    /// ```
+    pub fn is_cpu_online(&self, idx: usize) -> bool {
+        idx < self.as_slice().len()
+    }
+
+    /// Get a summary of the SMP topology.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{SmpTopology, Stivale2StructTagSmp};
+    ///
+    /// let smp = Stivale2StructTagSmp {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     flags: 1,
+    ///     bsp_lapic_id: 2,
+    ///     unused: 0,
+    ///     cpu_count: 4,
+    ///     smp_info: [],
+    /// };
+    ///
+    /// assert_eq!(
+    ///     smp.topology(),
+    ///     SmpTopology {
+    ///         cpu_count: 4,
+    ///         bsp_lapic_id: 2,
+    ///         x2apic: true,
+    ///     }
+    /// );
+    /// ```
+    pub fn topology(&self) -> SmpTopology {
+        SmpTopology {
+            cpu_count: self.cpu_count,
+            bsp_lapic_id: self.bsp_lapic_id,
+            x2apic: self.flags & 1 != 0,
+        }
+    }
+}
+
+/// A summary of the SMP tag. Created by [`Stivale2StructTagSmp::topology`].
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+pub struct SmpTopology {
+    pub cpu_count: u64,
+    pub bsp_lapic_id: u32,
+    pub x2apic: bool,
+}
+
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2SmpInfo {
+    pub processor_id: u32,
+    pub lapic_id: u32,
+    pub target_stack: u64,
+    /// Written by the kernel to wake the AP up. The AP polls it, so it has to be written
+    /// atomically.
+    pub goto_address: core::sync::atomic::AtomicU64,
+    /// Passed to the AP along with `goto_address`. It's read by the AP after it's woken up, so
+    /// it's atomic as well.
+    pub extra_argument: core::sync::atomic::AtomicU64,
+}
+
+impl Stivale2SmpInfo {
+    /// Wake the AP up by atomically writing `entry` to `goto_address`. The AP jumps to `entry`
+    /// with a pointer to this structure as its only argument.
+    ///
+    /// # Safety
+    ///
+    /// `target_stack` is loaded as the stack pointer of the AP, so it must point to the top of a
+    /// stack the AP can use before waking it up. `entry` must be safe to run on the AP with nothing
+    /// but that stack and the state the bootloader left it in.
+    pub unsafe fn goto(&self, entry: extern "C" fn(*const Stivale2SmpInfo) -> !) {
+        self.goto_address
+            .store(entry as usize as u64, core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_TEXTMODE_ID: u64 = 0x38d74c23e0dca893;
+
+/// This tag reports to the kernel info about the VGA text mode the bootloader set up, if it
+/// didn't set up a framebuffer.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagTextMode {
+    pub identifier: u64,
+    pub next: u64,
+    pub address: u64,
+    pub unused: u16,
+    pub rows: u16,
+    pub cols: u16,
+    pub bytes_per_char: u16,
+}
+
+impl Stivale2StructTagTextMode {
+    /// Create a [`TextModeWriter`] that writes characters with the attribute byte `attribute`,
+    /// starting at the top left corner.
+    pub fn writer(&self, attribute: u8) -> TextModeWriter<'_> {
+        TextModeWriter {
+            text_mode: self,
+            attribute,
+            col: 0,
+            row: 0,
+        }
+    }
+}
+
+/// A console on top of the text mode buffer that implements `core::fmt::Write`. Created by
+/// [`Stivale2StructTagTextMode::writer`].
+///
+/// Characters wrap at the end of a row and the buffer is scrolled up once the cursor moves past
+/// the last row. Characters outside of ASCII are written as `?`.
+///
+/// # Example
+///
+/// ```
+/// use core::fmt::Write;
+/// use stivale_rs::v2::Stivale2StructTagTextMode;
+///
+/// let mut buffer = [0u8; 4 * 2 * 2];
+/// let text_mode = Stivale2StructTagTextMode {
+///     identifier: 0,
+///     next: 0,
+///     address: buffer.as_mut_ptr() as u64,
+///     unused: 0,
+///     rows: 2,
+///     cols: 4,
+///     bytes_per_char: 2,
+/// };
+///
+/// let mut writer = text_mode.writer(0x07);
+/// write!(writer, "ab\ncdefg").unwrap();
+///
+/// let chars: Vec<u8> = buffer.chunks(2).map(|cell| cell[0]).collect();
+/// assert_eq!(chars, b"cdefg   ");
+/// assert!(buffer.chunks(2).all(|cell| cell[1] == 0x07));
+/// ```
+pub struct TextModeWriter<'a> {
+    text_mode: &'a Stivale2StructTagTextMode,
+    attribute: u8,
+    col: u16,
+    row: u16,
+}
+
+impl TextModeWriter<'_> {
+    fn write_cell(&self, col: u16, row: u16, c: u8, attribute: u8) {
+        let cell = row as usize * self.text_mode.cols as usize + col as usize;
+        let ptr = self.text_mode.address as *mut u8;
+        let ptr = ptr.wrapping_add(cell * self.text_mode.bytes_per_char as usize);
+
+        unsafe {
+            core::ptr::write_volatile(ptr, c);
+            core::ptr::write_volatile(ptr.add(1), attribute);
+        }
+    }
+
+    fn read_cell(&self, col: u16, row: u16) -> (u8, u8) {
+        let cell = row as usize * self.text_mode.cols as usize + col as usize;
+        let ptr = self.text_mode.address as *const u8;
+        let ptr = ptr.wrapping_add(cell * self.text_mode.bytes_per_char as usize);
+
+        unsafe {
+            (
+                core::ptr::read_volatile(ptr),
+                core::ptr::read_volatile(ptr.add(1)),
+            )
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+
+        if self.row + 1 < self.text_mode.rows {
+            self.row += 1;
+            return;
+        }
+
+        for row in 1..self.text_mode.rows {
+            for col in 0..self.text_mode.cols {
+                let (c, attribute) = self.read_cell(col, row);
+                self.write_cell(col, row - 1, c, attribute);
+            }
+        }
+
+        for col in 0..self.text_mode.cols {
+            self.write_cell(col, self.row, b' ', self.attribute);
+        }
+    }
+}
+
+impl core::fmt::Write for TextModeWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.text_mode.rows == 0 || self.text_mode.cols == 0 {
+            return Err(core::fmt::Error);
+        }
+
+        for c in s.chars() {
+            if c == '\n' {
+                self.new_line();
+                continue;
+            }
+
+            if self.col == self.text_mode.cols {
+                self.new_line();
+            }
+
+            let c = if c.is_ascii() { c as u8 } else { b'?' };
+            self.write_cell(self.col, self.row, c, self.attribute);
+            self.col += 1;
+        }
+
+        Ok(())
+    }
+}
+
+pub const STIVALE2_STRUCT_TAG_HHDM_ID: u64 = 0xb0ed257db18cb58f;
+
+/// This tag reports the virtual address the bootloader direct mapped physical memory at.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagHhdm {
+    pub identifier: u64,
+    pub next: u64,
+    pub addr: u64,
+}
+
+pub const STIVALE2_STRUCT_TAG_PXE_SERVER_INFO_ID: u64 = 0x29d1e96239247032;
+
+/// This tag reports the IP address of the server the kernel was loaded from over PXE.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagPxeServerInfo {
+    pub identifier: u64,
+    pub next: u64,
+    /// The IPv4 address in network byte order.
+    pub server_ip: u32,
+}
+
+pub const STIVALE2_STRUCT_TAG_BOOT_VOLUME_ID: u64 = 0x9b4358364c19ee62;
+
+/// This tag reports the GUIDs of the volume the kernel was loaded from.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagBootVolume {
+    pub identifier: u64,
+    pub next: u64,
+    /// Bit 0 is set if `guid` is valid, bit 1 is set if `part_guid` is valid.
+    pub flags: u64,
+    pub guid: Stivale2Guid,
+    pub part_guid: Stivale2Guid,
+}
+
+impl Stivale2StructTagBootVolume {
+    /// Get the GUID of the whole boot device, if the bootloader reported it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::{Stivale2Guid, Stivale2StructTagBootVolume};
+    ///
+    /// let disk = Stivale2Guid { a: 1, b: 2, c: 3, d: [4; 8] };
+    /// let part = Stivale2Guid { a: 5, b: 6, c: 7, d: [8; 8] };
+    /// let volume = |flags| Stivale2StructTagBootVolume {
+    ///     identifier: 0,
+    ///     next: 0,
+    ///     flags,
+    ///     guid: disk,
+    ///     part_guid: part,
+    /// };
+    ///
+    /// assert_eq!(volume(0b11).disk_guid(), Some(disk));
+    /// assert_eq!(volume(0b11).part_guid(), Some(part));
+    /// assert_eq!(volume(0b10).disk_guid(), None);
+    /// assert_eq!(volume(0b01).part_guid(), None);
+    /// ```
+    pub fn disk_guid(&self) -> Option<Stivale2Guid> {
+        if self.flags & (1 << 0) != 0 {
+            Some(self.guid)
+        } else {
+            None
+        }
+    }
+
+    /// Get the GUID of the boot partition, if the bootloader reported it.
+    pub fn part_guid(&self) -> Option<Stivale2Guid> {
+        if self.flags & (1 << 1) != 0 {
+            Some(self.part_guid)
+        } else {
+            None
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+pub struct Stivale2Guid {
+    pub a: u32,
+    pub b: u16,
+    pub c: u16,
+    pub d: [u8; 8],
+}
+
+pub const STIVALE2_STRUCT_TAG_RSDP_ID: u64 = 0x9e1786930a375e78;
+
+/// This tag provides the kernel with the location of the ACPI RSDP structure.
+#[repr(C)]
+#[derive(core::fmt::Debug)]
+pub struct Stivale2StructTagRsdp {
+    pub identifier: u64,
+    pub next: u64,
+    pub rsdp: u64,
+}
+
+/// A 64 bit FNV-1a hasher.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_entry(&mut self, entry: &Stivale2MMapEntry) {
+        self.write(&entry.base.to_le_bytes());
+        self.write(&entry.length.to_le_bytes());
+        self.write(&entry.r#type.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Get the range of steps `u` for which `pos + step * u` is in `[0, size)`, where `step` is -1, 0
+/// or 1. Returns `None` if there is no such step.
+fn clip_axis(pos: i64, step: i64, size: i64) -> Option<(i128, i128)> {
+    let (pos, size) = (pos as i128, size as i128);
+    let range = match step {
+        0 if pos >= 0 && pos < size => (0, i128::from(u32::MAX)),
+        0 => return None,
+        1 => (-pos, size - 1 - pos),
+        _ => (pos - size + 1, pos),
+    };
+
+    if range.0 <= range.1 {
+        Some(range)
+    } else {
+        None
+    }
+}
+
+/// Round `value` up to a multiple of `align`, returning `None` on overflow or a zero `align`.
+fn align_up(value: u64, align: u64) -> Option<u64> {
+    if align == 0 {
+        return None;
+    }
+
+    Some(value.checked_add(align - 1)? / align * align)
+}
+
+/// Maps interrupt vectors to the LAPIC ID of the CPU that should service them.
+///
+/// Slot `n` of `routing` holds `(vector, lapic_id)` for vector `n`. Vector 0 is the divide error
+/// exception and can never be delivered by the IOAPIC, so a slot with vector 0 is unrouted.
+#[derive(Clone, Copy, core::fmt::Debug)]
+pub struct IrqAffinityRouter {
+    pub routing: [(u8, u32); 256],
+}
+
+impl IrqAffinityRouter {
+    /// Create a router with no routed vectors.
+    pub const fn new() -> Self {
+        IrqAffinityRouter {
+            routing: [(0, 0); 256],
+        }
+    }
+
+    /// Route `vector` to the CPU with the LAPIC ID `lapic_id`.
+    pub fn route_irq(&mut self, vector: u8, lapic_id: u32) {
+        self.routing[vector as usize] = (vector, lapic_id);
+    }
+
+    /// Get the LAPIC ID `vector` is routed to.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut router = stivale_rs::v2::IrqAffinityRouter::new();
+    /// router.route_irq(0x21, 3);
+    ///
+    /// assert_eq!(router.get_target_lapic(0x21), Some(3));
+    /// assert_eq!(router.get_target_lapic(0x22), None);
+    /// ```
+    pub fn get_target_lapic(&self, vector: u8) -> Option<u32> {
+        match self.routing[vector as usize] {
+            (0, _) => None,
+            (_, lapic_id) => Some(lapic_id),
+        }
+    }
+}
+
+impl Default for IrqAffinityRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Retarget the IOAPIC redirection table entries according to `router`.
+///
+/// Every redirection entry whose vector is routed gets its destination set to the routed LAPIC
+/// ID in physical destination mode. Entries with unrouted vectors are left untouched, as are
+/// entries routed to LAPIC IDs above 255, which don't fit in the 8 bit destination field. Those
+/// CPUs can only be reached through interrupt remapping.
+///
+/// Each entry is masked while its destination is changed, so an interrupt can't be delivered
+/// with a half updated entry.
+///
+/// # Safety
+///
+/// `ioapic_base` must point to the memory mapped registers of an IOAPIC.
+pub unsafe fn apply_routing_to_ioapic(router: &IrqAffinityRouter, ioapic_base: *mut u32) {
+    const IOAPICVER: u32 = 0x01;
+    const IOREDTBL: u32 = 0x10;
+    const MASKED: u32 = 1 << 16;
+    const LOGICAL_DEST_MODE: u32 = 1 << 11;
+
+    let regsel = ioapic_base;
+    let win = ioapic_base.add(4);
+
+    let read = |reg: u32| {
+        core::ptr::write_volatile(regsel, reg);
+        core::ptr::read_volatile(win)
+    };
+    let write = |reg: u32, value: u32| {
+        core::ptr::write_volatile(regsel, reg);
+        core::ptr::write_volatile(win, value);
+    };
+
+    let max_redir = (read(IOAPICVER) >> 16) & 0xff;
+
+    for pin in 0..=max_redir {
+        let low_reg = IOREDTBL + pin * 2;
+        let low = read(low_reg);
+
+        let lapic_id = match router.get_target_lapic(low as u8) {
+            Some(lapic_id) if lapic_id <= 0xff => lapic_id,
+            _ => continue,
+        };
+
+        write(low_reg, low | MASKED);
+        write(low_reg + 1, lapic_id << 24);
+        write(low_reg, low & !LOGICAL_DEST_MODE);
+    }
+}
+
+/// An entry of the MCFG table describing the ECAM region of a PCIe segment group.
+#[repr(C, packed)]
+#[derive(Clone, Copy, core::fmt::Debug)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+    pub reserved: u32,
+}
+
+/// Iterator over the entries of an ACPI MCFG table.
+pub struct McfgIter {
+    current: *const McfgEntry,
+    end: *const McfgEntry,
+}
+
+impl McfgIter {
+    /// Create an iterator over the entries of the MCFG table at `mcfg`.
+    ///
+    /// # Safety
+    ///
+    /// `mcfg` must point to a valid MCFG table.
+    pub unsafe fn new(mcfg: *const u8) -> Self {
+        // The table length lives at offset 4 of the SDT header and the entries start after the
+        // 36 byte header and 8 reserved bytes.
+        let length = core::ptr::read_unaligned(mcfg.add(4) as *const u32) as usize;
+        let count = length.saturating_sub(44) / core::mem::size_of::<McfgEntry>();
+        let current = mcfg.add(44) as *const McfgEntry;
+
+        McfgIter {
+            current,
+            end: current.add(count),
+        }
+    }
+}
+
+impl Iterator for McfgIter {
+    type Item = McfgEntry;
+
+    fn next(&mut self) -> Option<McfgEntry> {
+        if self.current >= self.end {
+            return None;
+        }
+
+        let entry = unsafe { core::ptr::read_unaligned(self.current) };
+        self.current = unsafe { self.current.add(1) };
+        Some(entry)
+    }
+}
+
+/// A PCIe function discovered through the ECAM.
+#[derive(Clone, Copy, core::fmt::Debug)]
+pub struct PcieDevice {
+    pub segment: u16,
+    pub bus: u8,
+    pub dev: u8,
+    pub func: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    /// Pointer to the 4KiB configuration space of the function.
+    pub config_ptr: *mut u8,
+}
+
+/// Iterator over every present PCIe function. Created by [`enumerate_pcie`].
+pub struct PcieDevIter {
+    mcfg: McfgIter,
+    entry: Option<McfgEntry>,
+    bus: u16,
+    dev: u8,
+    func: u8,
+}
+
+impl PcieDevIter {
+    /// Move to the next function slot, skipping functions 1-7 unless `multifunction` is set.
+    fn advance(&mut self, multifunction: bool) {
+        if self.func < 7 && multifunction {
+            self.func += 1;
+            return;
+        }
+
+        self.func = 0;
+        if self.dev < 31 {
+            self.dev += 1;
+            return;
+        }
+
+        self.dev = 0;
+        self.bus += 1;
+    }
+}
+
+impl Iterator for PcieDevIter {
+    type Item = PcieDevice;
+
+    fn next(&mut self) -> Option<PcieDevice> {
+        loop {
+            let entry = match self.entry {
+                Some(entry) if self.bus <= entry.end_bus as u16 => entry,
+                _ => {
+                    let entry = self.mcfg.next()?;
+                    self.entry = Some(entry);
+                    self.bus = entry.start_bus as u16;
+                    self.dev = 0;
+                    self.func = 0;
+                    continue;
+                }
+            };
+
+            let (bus, dev, func) = (self.bus as u8, self.dev, self.func);
+            let offset = ((bus as u64) << 20) | ((dev as u64) << 15) | ((func as u64) << 12);
+            let config_ptr = (entry.base_address + offset) as *mut u8;
+
+            let read16 =
+                |off: usize| unsafe { core::ptr::read_volatile(config_ptr.add(off) as *const u16) };
+            let read8 = |off: usize| unsafe { core::ptr::read_volatile(config_ptr.add(off)) };
+
+            let vendor_id = read16(0x00);
+            if vendor_id == 0xffff {
+                // A missing function 0 means the whole device is absent.
+                self.advance(func != 0);
+                continue;
+            }
+
+            // Only probe functions 1-7 when function 0 reports a multi-function device.
+            let multifunction = func != 0 || read8(0x0e) & 0x80 != 0;
+            self.advance(multifunction);
+
+            return Some(PcieDevice {
+                segment: entry.segment_group,
+                bus,
+                dev,
+                func,
+                vendor_id,
+                device_id: read16(0x02),
+                class: read8(0x0b),
+                subclass: read8(0x0a),
+                config_ptr,
+            });
+        }
+    }
+}
+
+/// Enumerate every PCIe function present in the segment groups described by `mcfg_iter`.
+///
+/// The ECAM regions are accessed at the physical addresses reported by the MCFG, so they must be
+/// identity mapped.
+///
+/// # Example
+///
+/// ```
+/// use stivale_rs::v2::{enumerate_pcie, McfgIter};
+///
+/// // One bus worth of ECAM space with a single device at 00:01.0.
+/// let mut ecam = vec![0xffu8; 1 << 20];
+/// let config = &mut ecam[1 << 15..];
+/// config[0..4].copy_from_slice(&[0x86, 0x80, 0x34, 0x12]);
+/// config[0x0a] = 0x06;
+/// config[0x0b] = 0x01;
+/// config[0x0e] = 0x00;
+///
+/// let mut mcfg = [0u8; 60];
+/// mcfg[0..4].copy_from_slice(b"MCFG");
+/// mcfg[4..8].copy_from_slice(&60u32.to_le_bytes());
+/// mcfg[44..52].copy_from_slice(&(ecam.as_ptr() as u64).to_le_bytes());
+///
+/// let mut devices = enumerate_pcie(unsafe { McfgIter::new(mcfg.as_ptr()) });
+/// let dev = devices.next().unwrap();
+///
+/// assert_eq!((dev.bus, dev.dev, dev.func), (0, 1, 0));
+/// assert_eq!((dev.vendor_id, dev.device_id), (0x8086, 0x1234));
+/// assert_eq!((dev.class, dev.subclass), (0x01, 0x06));
+/// assert!(devices.next().is_none());
+/// ```
+pub fn enumerate_pcie(mcfg_iter: McfgIter) -> PcieDevIter {
+    PcieDevIter {
+        mcfg: mcfg_iter,
+        entry: None,
+        bus: 0,
+        dev: 0,
+        func: 0,
+    }
+}
+
+/// Mark every module and the kernel image as `KernelAndModules` in `memmap`, splitting usable
+/// entries as necessary.
+///
+/// The kernel base address tag doesn't report the size of the kernel image, so it has to be
+/// passed as `kernel_size`. This should be done before reclaiming any bootloader memory so
+/// that the kernel and modules are never handed out by the allocator.
+///
+/// Returns false without modifying the map if `memmap` doesn't have enough spare entries for
+/// the split entries, or if it holds more entries than `ENTRIES_LEN`. See
+/// [`Stivale2StructTagMemmap::reserve_range`].
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::*;
+///
+/// fn entry(
+///     memmap: &mut Stivale2StructTagMemmap<64>,
+///     modules: &Stivale2StructTagModules<16>,
+///     kernel_base: &Stivale2StructTagKernelBaseAddress,
+///     kernel_size: u64,
+/// ) {
+///     assert!(reserve_modules_and_kernel(memmap, modules, kernel_base, kernel_size));
+///     // It's safe to reclaim bootloader memory from here on.
+/// }
+/// ```
+pub fn reserve_modules_and_kernel<const ENTRIES_LEN: usize, const MODULES_LEN: usize>(
+    memmap: &mut Stivale2StructTagMemmap<ENTRIES_LEN>,
+    modules: &Stivale2StructTagModules<MODULES_LEN>,
+    kernel_base: &Stivale2StructTagKernelBaseAddress,
+    kernel_size: u64,
+) -> bool {
+    let kernel = (kernel_base.physical_base_address, kernel_size);
+    let ranges = || {
+        modules
+            .as_slice()
+            .iter()
+            .map(|m| (m.begin, m.end.saturating_sub(m.begin)))
+            .chain(core::iter::once(kernel))
+            .filter(|&(_, length)| length != 0)
+    };
+
+    // Count the split entries up front so that the map is never left half updated. Only the ends
+    // of a range can split an entry, if they're inside of a usable entry and not on or inside of
+    // a range reserved before.
+    let mut extra = 0u64;
+    for (i, (base, length)) in ranges().enumerate() {
+        let end = match base.checked_add(length) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        for point in [base, end] {
+            let splits = memmap
+                .as_slice()
+                .iter()
+                .any(|e| e.is_usable() && e.base < point && point - e.base < e.length);
+            let reserved = ranges()
+                .take(i)
+                .any(|(base, length)| base <= point && point <= base + length);
+
+            if splits && !reserved {
+                extra += 1;
+            }
+        }
+    }
+
+    if memmap.entries.saturating_add(extra) > ENTRIES_LEN as u64 {
+        return false;
+    }
+
+    ranges().all(|(base, length)| {
+        memmap.reserve_range(base, length, Stivale2MMapType::KernelAndModules)
+    })
+}
+
+/// A set of CPUs, indexed by their position in the SMP tag.
+#[derive(Clone, Copy, Default, PartialEq, Eq, core::fmt::Debug)]
+pub struct CpuAffinityMask {
+    pub bits: [u64; 4],
+}
+
+impl CpuAffinityMask {
+    /// Create an empty mask.
+    pub const fn new() -> Self {
+        CpuAffinityMask { bits: [0; 4] }
+    }
+
+    /// Add the CPU at `idx` to the mask. Indices past 255 are ignored.
+    pub fn set(&mut self, idx: usize) {
+        if let Some(word) = self.bits.get_mut(idx / 64) {
+            *word |= 1 << (idx % 64);
+        }
+    }
+
+    /// Check whether the CPU at `idx` is in the mask.
+    pub fn contains(&self, idx: usize) -> bool {
+        match self.bits.get(idx / 64) {
+            Some(word) => word & (1 << (idx % 64)) != 0,
+            None => false,
+        }
+    }
+}
+
+/// Wake every AP that isn't in `active_mask` with `halt_fn`, leaving the active APs parked for
+/// the kernel to wake up later. The BSP is never woken up.
+///
+/// `halt_fn` should do nothing but execute `hlt` in a loop.
+///
+/// # Safety
+///
+/// `target_stack` is left untouched and is often 0, so it must point to a usable stack for every
+/// AP that gets woken up, unless `halt_fn` never touches the stack at all. `halt_fn` has to be
+/// written in assembly to guarantee that, as even an empty Rust function may push to the stack.
+///
+/// # Example
+///
+/// ```
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use stivale_rs::v2::*;
+///
+/// extern "C" fn halt(_: *const Stivale2SmpInfo) -> ! {
+///     loop {}
+/// }
+///
+/// let cpu = |lapic_id| Stivale2SmpInfo {
+///     processor_id: lapic_id,
+///     lapic_id,
+///     target_stack: 0,
+///     goto_address: AtomicU64::new(0),
+///     extra_argument: AtomicU64::new(0),
+/// };
+/// let smp = Stivale2StructTagSmp {
+///     identifier: STIVALE2_STRUCT_TAG_SMP_ID,
+///     next: 0,
+///     flags: 0,
+///     bsp_lapic_id: 0,
+///     unused: 0,
+///     cpu_count: 3,
+///     smp_info: [cpu(0), cpu(1), cpu(2)],
+/// };
+///
+/// let mut active = CpuAffinityMask::new();
+/// active.set(1);
+/// // Nothing is actually woken up here, so `target_stack` doesn't matter.
+/// unsafe { halt_idle_aps(&smp, active, halt) };
+///
+/// let goto = |idx: usize| smp.smp_info[idx].goto_address.load(Ordering::SeqCst);
+/// assert_eq!(goto(0), 0);
+/// assert_eq!(goto(1), 0);
+/// assert_eq!(goto(2), halt as usize as u64);
+/// ```
+pub unsafe fn halt_idle_aps<const CPU_COUNT: usize>(
+    smp: &Stivale2StructTagSmp<CPU_COUNT>,
+    active_mask: CpuAffinityMask,
+    halt_fn: extern "C" fn(*const Stivale2SmpInfo) -> !,
+) {
+    for (idx, cpu) in smp.as_slice().iter().enumerate() {
+        if cpu.lapic_id != smp.bsp_lapic_id && !active_mask.contains(idx) {
+            cpu.goto(halt_fn);
+        }
+    }
+}
+
+/// Configure HPET comparator `comparator` to fire an interrupt every `period_fs` femtoseconds
+/// and start the main counter if it isn't running.
+///
+/// The comparator is put into periodic mode if it supports it and one-shot mode otherwise. It's
+/// armed relative to the current value of the main counter, which is neither halted nor reset,
+/// so other comparators and timekeeping based on the main counter aren't disturbed. The interrupt
+/// routing of the comparator is left as configured by the firmware.
+///
+/// Returns the frequency of the HPET main counter in Hz, or 0 if the HPET doesn't have the
+/// requested comparator.
+///
+/// # Safety
+///
+/// `hpet_base` must be the address of the memory mapped HPET registers.
+///
+/// # Example
+///
+/// ```
+/// let mut hpet = [0u64; 64];
+/// // 10ns counter period, 3 comparators.
+/// hpet[0] = (10_000_000 << 32) | (2 << 8);
+/// // Comparator 1 supports periodic mode.
+/// hpet[0x120 / 8] = 1 << 4;
+/// // The main counter is already running.
+/// hpet[0x10 / 8] = 1;
+/// hpet[0xf0 / 8] = 5_000;
+///
+/// let freq = unsafe {
+///     stivale_rs::v2::configure_hpet_comparator(hpet.as_mut_ptr() as u64, 1, 1_000_000_000_000)
+/// };
+///
+/// assert_eq!(freq, 100_000_000);
+/// assert_eq!(hpet[0x128 / 8], 100_000);
+/// assert_eq!(hpet[0xf0 / 8], 5_000);
+/// assert_eq!(hpet[0x10 / 8] & 1, 1);
+/// ```
+pub unsafe fn configure_hpet_comparator(hpet_base: u64, comparator: u8, period_fs: u64) -> u64 {
+    const GENERAL_CAPABILITIES: usize = 0x000;
+    const GENERAL_CONFIGURATION: usize = 0x010;
+    const MAIN_COUNTER: usize = 0x0f0;
+
+    const COUNT_SIZE_CAP: u64 = 1 << 13;
+    const ENABLE_CNF: u64 = 1 << 0;
+    const TN_INT_ENB_CNF: u64 = 1 << 2;
+    const TN_TYPE_CNF: u64 = 1 << 3;
+    const TN_PER_INT_CAP: u64 = 1 << 4;
+    const TN_VAL_SET_CNF: u64 = 1 << 6;
+
+    let reg = |offset: usize| (hpet_base as usize + offset) as *mut u64;
+
+    let capabilities = core::ptr::read_volatile(reg(GENERAL_CAPABILITIES));
+    let counter_period = capabilities >> 32;
+    let num_comparators = ((capabilities >> 8) & 0x1f) + 1;
+
+    if counter_period == 0 || comparator as u64 >= num_comparators {
+        return 0;
+    }
+
+    let timer_config = reg(0x100 + 0x20 * comparator as usize);
+    let timer_comparator = reg(0x108 + 0x20 * comparator as usize);
+
+    // Comparators of a 32 bit main counter are 32 bits wide as well.
+    let counter_mask = if capabilities & COUNT_SIZE_CAP != 0 {
+        u64::MAX
+    } else {
+        u32::MAX as u64
+    };
+    let ticks = core::cmp::max(period_fs / counter_period, 1);
+    let first = core::ptr::read_volatile(reg(MAIN_COUNTER)).wrapping_add(ticks) & counter_mask;
+    let mut timer = core::ptr::read_volatile(timer_config) | TN_INT_ENB_CNF;
+
+    if timer & TN_PER_INT_CAP != 0 {
+        // In periodic mode the first write sets the comparator and, with TN_VAL_SET_CNF set, the
+        // second write sets the period the comparator is incremented by.
+        timer |= TN_TYPE_CNF | TN_VAL_SET_CNF;
+        core::ptr::write_volatile(timer_config, timer);
+        core::ptr::write_volatile(timer_comparator, first);
+        core::ptr::write_volatile(timer_comparator, ticks);
+    } else {
+        timer &= !TN_TYPE_CNF;
+        core::ptr::write_volatile(timer_config, timer);
+        core::ptr::write_volatile(timer_comparator, first);
+    }
+
+    let config = core::ptr::read_volatile(reg(GENERAL_CONFIGURATION));
+    if config & ENABLE_CNF == 0 {
+        core::ptr::write_volatile(reg(GENERAL_CONFIGURATION), config | ENABLE_CNF);
+    }
+
+    1_000_000_000_000_000 / counter_period
+}
+
+/// The result of [`check_stack_safety`].
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+pub enum StackSafetyResult {
+    /// The stack is backed by RAM and the page below it isn't usable memory.
+    Safe,
+    /// The page below the stack overlaps the usable entry at `overlapping_entry`, so an overflow
+    /// would silently corrupt memory instead of faulting.
+    GuardPageOverlapsUsable { overlapping_entry: usize },
+    /// Part of the stack isn't backed by usable, bootloader reclaimable or kernel memory.
+    StackInNonUsableRegion,
+}
+
+/// Check whether a kernel stack spanning `[stack_base, stack_base + stack_size)` can be used
+/// safely.
+///
+/// The stack grows down towards `stack_base`, so the 4KiB page right below it has to be left
+/// out of the allocator and unmapped to act as a guard page. A stack placed in the kernel image
+/// (e.g. in `.bss`) is fine as long as the page below it isn't usable memory.
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::*;
+///
+/// fn entry(memmap: &Stivale2StructTagMemmap<64>, stack_base: u64, stack_size: u64) {
+///     assert_eq!(check_stack_safety(memmap, stack_base, stack_size), StackSafetyResult::Safe);
+/// }
+/// ```
+pub fn check_stack_safety<const ENTRIES_LEN: usize>(
+    memmap: &Stivale2StructTagMemmap<ENTRIES_LEN>,
+    stack_base: u64,
+    stack_size: u64,
+) -> StackSafetyResult {
+    const GUARD_PAGE_SIZE: u64 = 0x1000;
+
+    let is_ram = |e: &Stivale2MMapEntry| {
+        e.is_usable()
+            || e.r#type == Stivale2MMapType::BootloaderReclaimable as u32
+            || e.r#type == Stivale2MMapType::KernelAndModules as u32
+    };
+
+    if !memmap.is_range_covered(stack_base, stack_size, is_ram) {
+        return StackSafetyResult::StackInNonUsableRegion;
+    }
+
+    let guard_base = stack_base.saturating_sub(GUARD_PAGE_SIZE);
+    let overlapping_entry = memmap.as_slice().iter().position(|e| {
+        e.is_usable() && e.base < stack_base && guard_base < e.base.saturating_add(e.length)
+    });
+
+    match overlapping_entry {
+        Some(overlapping_entry) => StackSafetyResult::GuardPageOverlapsUsable { overlapping_entry },
+        None => StackSafetyResult::Safe,
+    }
+}
+
+/// Enable the local APIC of the calling CPU. This should be called at the top of every AP entry
+/// function, and on the BSP, before the CPU is expected to receive interrupts.
+///
+/// The registers are programmed in the following order:
+///
+/// 1. The spurious interrupt vector register is set to `spurious_vector` with the APIC software
+///    enable bit set. Until the APIC is software enabled, writes to the LVT are ignored and
+///    every local interrupt stays masked.
+/// 2. The task priority register is set to 0 so that no interrupt class is blocked.
+/// 3. The error status register is cleared. It only latches new errors after being written, so
+///    it is written twice to discard errors recorded before the APIC was enabled.
+///
+/// `spurious_vector` should have its low 4 bits set, as older processors hardwire them to 1.
+///
+/// # Safety
+///
+/// `lapic_base` must be the address of the memory mapped local APIC registers of the calling
+/// CPU.
+///
+/// # Example
+///
+/// ```
+/// let mut lapic = [0u32; 0x400 / 4];
+/// unsafe { stivale_rs::v2::init_local_apic(lapic.as_mut_ptr() as u64, 0xff) };
+///
+/// assert_eq!(lapic[0xf0 / 4], 0x1ff);
+/// assert_eq!(lapic[0x80 / 4], 0);
+/// ```
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn init_local_apic(lapic_base: u64, spurious_vector: u8) {
+    const TPR: usize = 0x080;
+    const SVR: usize = 0x0f0;
+    const ESR: usize = 0x280;
+    const SVR_APIC_ENABLE: u32 = 1 << 8;
+
+    let reg = |offset: usize| (lapic_base as usize + offset) as *mut u32;
+
+    core::ptr::write_volatile(reg(SVR), spurious_vector as u32 | SVR_APIC_ENABLE);
+    core::ptr::write_volatile(reg(TPR), 0);
+    core::ptr::write_volatile(reg(ESR), 0);
+    core::ptr::write_volatile(reg(ESR), 0);
+}
+
+/// A PC Screen Font version 2 font, the format of the Linux console fonts.
+pub struct Stivale2Psf2Font<'a> {
+    glyphs: &'a [u8],
+    unicode_table: Option<&'a [u8]>,
+    num_glyphs: usize,
+    bytes_per_glyph: usize,
+    height: u32,
+    width: u32,
+}
+
+impl<'a> Stivale2Psf2Font<'a> {
+    /// Parse a PSF2 font from `data`, e.g. the contents of a module. Returns `None` if `data` is
+    /// not a valid PSF2 font, including fonts without glyphs or with empty glyphs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stivale_rs::v2::Stivale2Psf2Font;
+    ///
+    /// let font = |width: u32| {
+    ///     let mut font = Vec::new();
+    ///     for field in [0x864ab572u32, 0, 32, 0, 1, 1, 1, width] {
+    ///         font.extend_from_slice(&field.to_le_bytes());
+    ///     }
+    ///     font.push(0x80);
+    ///     font
+    /// };
+    ///
+    /// assert!(Stivale2Psf2Font::new(&font(1)).is_some());
+    /// assert!(Stivale2Psf2Font::new(&font(0)).is_none());
+    /// ```
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        const PSF2_MAGIC: u32 = 0x864ab572;
+        const PSF2_HAS_UNICODE_TABLE: u32 = 1;
+
+        let field = |idx: usize| {
+            let bytes = data.get(idx * 4..idx * 4 + 4)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+
+        if field(0)? != PSF2_MAGIC {
+            return None;
+        }
+
+        let header_size = field(2)? as usize;
+        let flags = field(3)?;
+        let num_glyphs = field(4)? as usize;
+        let bytes_per_glyph = field(5)? as usize;
+        let height = field(6)?;
+        let width = field(7)?;
+
+        if num_glyphs == 0 || width == 0 || height == 0 {
+            return None;
+        }
+        if bytes_per_glyph < (width as usize).div_ceil(8) * height as usize {
+            return None;
+        }
+
+        let glyphs_end = header_size.checked_add(num_glyphs.checked_mul(bytes_per_glyph)?)?;
+        let glyphs = data.get(header_size..glyphs_end)?;
+        let unicode_table = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            Some(&data[glyphs_end..])
+        } else {
+            None
+        };
+
+        Some(Stivale2Psf2Font {
+            glyphs,
+            unicode_table,
+            num_glyphs,
+            bytes_per_glyph,
+            height,
+            width,
+        })
+    }
+
+    /// Get the width of a glyph in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of a glyph in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the index of the glyph for `c`.
+    ///
+    /// Fonts without a unicode table are indexed by codepoint. Characters without a glyph fall
+    /// back to glyph 0.
+    fn glyph_index(&self, c: char) -> usize {
+        let table = match self.unicode_table {
+            Some(table) => table,
+            None if (c as usize) < self.num_glyphs => return c as usize,
+            None => return 0,
+        };
+
+        // Every glyph has a list of UTF-8 encoded codepoints, followed by 0xfe separated
+        // multi-codepoint sequences and terminated by 0xff. Neither byte appears in UTF-8.
+        table
+            .split(|&byte| byte == 0xff)
+            .take(self.num_glyphs)
+            .position(|entry| {
+                let codepoints = entry.split(|&byte| byte == 0xfe).next().unwrap_or(&[]);
+                core::str::from_utf8(codepoints).is_ok_and(|s| s.chars().any(|ch| ch == c))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Render the glyph for `c` with its top left corner at (`x`, `y`), using the raw pixel
+    /// values `fg` and `bg`. Pixels outside of the framebuffer are clipped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stivale_rs::v2::{Stivale2Psf2Font, Stivale2StructTagFramebuffer};
+    ///
+    /// fn entry(fb: &Stivale2StructTagFramebuffer, font: &[u8]) {
+    ///     let font = Stivale2Psf2Font::new(font).expect("invalid font");
+    ///
+    ///     for (i, c) in "Hello".chars().enumerate() {
+    ///         font.render_char(fb, c, i as u16 * font.width() as u16, 0, 0xffffff, 0);
+    ///     }
+    /// }
+    /// ```
+    pub fn render_char(
+        &self,
+        fb: &Stivale2StructTagFramebuffer,
+        c: char,
+        x: u16,
+        y: u16,
+        fg: u32,
+        bg: u32,
+    ) {
+        let start = self.glyph_index(c) * self.bytes_per_glyph;
+        let glyph = &self.glyphs[start..start + self.bytes_per_glyph];
+        let bytes_per_row = (self.width as usize).div_ceil(8);
+
+        for (row, bits) in glyph
+            .chunks(bytes_per_row)
+            .take(self.height as usize)
+            .enumerate()
+        {
+            for col in 0..self.width as usize {
+                let set = bits[col / 8] & (0x80 >> (col % 8)) != 0;
+                let color = if set { fg } else { bg };
+
+                fb.write_pixel(x as u32 + col as u32, y as u32 + row as u32, color);
+            }
+        }
+    }
+}
+
+/// A memory map entry in the format returned by the BIOS E820 call.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default, core::fmt::Debug)]
+pub struct E820Entry {
+    pub base: u64,
+    pub length: u64,
+    pub type_: u32,
+}
+
+/// Convert `memmap` to the E820 format, writing as many entries as fit into `buf`.
+///
+/// Usable, ACPI reclaimable, ACPI NVS and bad memory map to their E820 counterparts (1, 3, 4 and
+/// 5). Every other type, including bootloader reclaimable memory and the kernel and modules,
+/// is reported as reserved (2) so that code consuming the E820 map never hands it out.
+///
+/// Returns the number of entries written.
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::{to_e820, E820Entry, Stivale2StructTagMemmap};
+///
+/// fn entry(memmap: &Stivale2StructTagMemmap<64>) {
+///     let mut e820 = [E820Entry::default(); 64];
+///     let len = to_e820(memmap, &mut e820);
+///     // Pass `&e820[..len]` to code expecting a BIOS memory map.
+/// }
+/// ```
+pub fn to_e820<const ENTRIES_LEN: usize>(
+    memmap: &Stivale2StructTagMemmap<ENTRIES_LEN>,
+    buf: &mut [E820Entry],
+) -> usize {
+    let mut written = 0;
+
+    for (entry, e820) in memmap.as_slice().iter().zip(buf.iter_mut()) {
+        let type_ = match entry.r#type {
+            t if t == Stivale2MMapType::Usable as u32 => 1,
+            t if t == Stivale2MMapType::ACPIReclaimable as u32 => 3,
+            t if t == Stivale2MMapType::ACPINvs as u32 => 4,
+            t if t == Stivale2MMapType::BadMemory as u32 => 5,
+            _ => 2,
+        };
+
+        *e820 = E820Entry {
+            base: entry.base,
+            length: entry.length,
+            type_,
+        };
+        written += 1;
+    }
+
+    written
+}
+
+/// Write `value` to the model specific register `msr`.
+#[cfg(target_arch = "x86_64")]
+unsafe fn wrmsr(msr: u32, value: u64) {
+    core::arch::asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
+
+/// Read the model specific register `msr`.
+#[cfg(target_arch = "x86_64")]
+unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    core::arch::asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nostack, nomem, preserves_flags),
+    );
+    ((high as u64) << 32) | low as u64
+}
+
+/// The architectural performance monitoring counters of the CPU.
+#[derive(Clone, Copy, PartialEq, Eq, core::fmt::Debug)]
+pub struct PmuConfig {
+    pub fixed_counters: u8,
+    pub gp_counters: u8,
+}
+
+/// Get the architectural performance monitoring version from CPUID leaf 0xA, which is 0 if the
+/// CPU doesn't support it.
+#[cfg(target_arch = "x86_64")]
+fn pmu_version() -> u8 {
+    use core::arch::x86_64::__cpuid;
+
+    if __cpuid(0).eax < 0xa {
+        return 0;
+    }
+
+    __cpuid(0xa).eax as u8
+}
+
+/// Detect the performance monitoring counters of the calling CPU using CPUID leaf 0xA.
+///
+/// # Example
+///
+/// ```
+/// let pmu = stivale_rs::v2::detect_pmu_capabilities();
+/// println!("{} fixed and {} general purpose counters", pmu.fixed_counters, pmu.gp_counters);
+/// ```
+#[cfg(target_arch = "x86_64")]
+pub fn detect_pmu_capabilities() -> PmuConfig {
+    let version = pmu_version();
+    if version == 0 {
+        return PmuConfig {
+            fixed_counters: 0,
+            gp_counters: 0,
+        };
+    }
+
+    let leaf = core::arch::x86_64::__cpuid(0xa);
+
+    PmuConfig {
+        // Fixed counters are only enumerated from version 2 onwards.
+        fixed_counters: if version >= 2 {
+            (leaf.edx & 0x1f) as u8
+        } else {
+            0
+        },
+        gp_counters: (leaf.eax >> 8) as u8,
+    }
+}
+
+/// Enable every performance monitoring counter of the calling CPU, counting in both kernel and
+/// user mode. This should be called from the AP entry function of the CPU with the LAPIC ID
+/// `lapic_id`.
+///
+/// The fixed counters are enabled through `IA32_FIXED_CTR_CTRL` and all counters are globally
+/// enabled through `IA32_PERF_GLOBAL_CTRL`. The general purpose counters still have to be
+/// programmed with an event to count anything. Nothing is done on CPUs with a performance
+/// monitoring version below 2, as they lack the global control MSR.
+///
+/// # Safety
+///
+/// Must be called in ring 0.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn enable_pmu_on_ap(lapic_id: u32) {
+    const IA32_FIXED_CTR_CTRL: u32 = 0x38d;
+    const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+    debug_assert_eq!(
+        core::arch::x86_64::__cpuid(1).ebx >> 24,
+        lapic_id & 0xff,
+        "enable_pmu_on_ap called on the wrong CPU"
+    );
+
+    if pmu_version() < 2 {
+        return;
+    }
+
+    let pmu = detect_pmu_capabilities();
+
+    // Every fixed counter has a 4 bit control field, 0b11 counts in both ring 0 and ring 3.
+    let fixed_ctrl = (0..core::cmp::min(pmu.fixed_counters, 16) as u64)
+        .fold(0, |ctrl, i| ctrl | 0b11 << (i * 4));
+    let mask = |count: u8| (1u64 << core::cmp::min(count, 32)) - 1;
+
+    wrmsr(IA32_FIXED_CTR_CTRL, fixed_ctrl);
+    wrmsr(
+        IA32_PERF_GLOBAL_CTRL,
+        (mask(pmu.fixed_counters) << 32) | mask(pmu.gp_counters),
+    );
+}
+
+/// Reserve the kernel image `[kernel_start, kernel_end)` as `KernelAndModules`, expanded to
+/// 2MiB boundaries so that the kernel can be mapped with huge pages.
+///
+/// Returns the expanded range, or `None` if `memmap` ran out of spare entries while splitting or
+/// holds more entries than `ENTRIES_LEN`.
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::{reserve_kernel_huge_page_aligned, Stivale2StructTagMemmap};
+///
+/// fn entry(memmap: &mut Stivale2StructTagMemmap<64>, kernel_start: u64, kernel_end: u64) {
+///     let (start, end) = reserve_kernel_huge_page_aligned(memmap, kernel_start, kernel_end)
+///         .expect("memory map full");
+///     // Map `start..end` with 2MiB pages.
+/// }
+/// ```
+pub fn reserve_kernel_huge_page_aligned<const ENTRIES_LEN: usize>(
+    memmap: &mut Stivale2StructTagMemmap<ENTRIES_LEN>,
+    kernel_start: u64,
+    kernel_end: u64,
+) -> Option<(u64, u64)> {
+    const HUGE_PAGE_SIZE: u64 = 0x200000;
+
+    let start = kernel_start & !(HUGE_PAGE_SIZE - 1);
+    let end = align_up(kernel_end, HUGE_PAGE_SIZE)?;
+
+    if memmap.reserve_range(
+        start,
+        end.saturating_sub(start),
+        Stivale2MMapType::KernelAndModules,
+    ) {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// A barrier that makes CPUs spin until `total` of them have reached it, e.g. to make sure every
+/// CPU finished one initialization phase before any starts the next one. For all CPUs, `total`
+/// is the `cpu_count` of the SMP tag.
+///
+/// The barrier can't be reused until it's [`reset`](SpinBarrier::reset).
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicU32, Ordering};
+/// use stivale_rs::v2::SpinBarrier;
+///
+/// static BARRIER: SpinBarrier = SpinBarrier::new(4);
+/// static PHASE_ONE: AtomicU32 = AtomicU32::new(0);
+///
+/// let threads: Vec<_> = (0..4)
+///     .map(|_| {
+///         std::thread::spawn(|| {
+///             PHASE_ONE.fetch_add(1, Ordering::SeqCst);
+///             BARRIER.wait();
+///             assert_eq!(PHASE_ONE.load(Ordering::SeqCst), 4);
+///         })
+///     })
+///     .collect();
+///
+/// for thread in threads {
+///     thread.join().unwrap();
+/// }
+/// ```
+pub struct SpinBarrier {
+    count: core::sync::atomic::AtomicU32,
+    total: u32,
+}
+
+impl SpinBarrier {
+    /// Create a barrier for `n` CPUs.
+    pub const fn new(n: u32) -> Self {
+        SpinBarrier {
+            count: core::sync::atomic::AtomicU32::new(0),
+            total: n,
+        }
+    }
+
+    /// Wait until `total` CPUs have called `wait`.
+    pub fn wait(&self) {
+        use core::sync::atomic::Ordering;
+
+        self.count.fetch_add(1, Ordering::AcqRel);
+
+        while self.count.load(Ordering::Acquire) < self.total {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Reset the barrier so it can be used again.
+    pub fn reset(&mut self) {
+        *self.count.get_mut() = 0;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const IA32_GS_BASE: u32 = 0xc0000101;
+
+/// Point the GS segment base of the calling CPU at `per_cpu_data`, so that per CPU data can be
+/// reached through `gs` or [`current_cpu_data`]. This should be called from the AP entry function
+/// of every CPU, and on the BSP.
+///
+/// The value is written to the `IA32_GS_BASE` MSR (0xc0000101) as a 64 bit linear address, with
+/// the low 32 bits in EAX and the high 32 bits in EDX. The address must be canonical, otherwise
+/// `wrmsr` raises a general protection fault.
+///
+/// # Safety
+///
+/// Must be called in ring 0.
+///
+/// # Example
+///
+/// ```no_run
+/// struct PerCpu {
+///     lapic_id: u32,
+/// }
+///
+/// extern "C" fn ap_entry(info: *const stivale_rs::v2::Stivale2SmpInfo) -> ! {
+///     let info = unsafe { &*info };
+///     let data = info.extra_argument.load(core::sync::atomic::Ordering::SeqCst) as *const PerCpu;
+///
+///     unsafe {
+///         stivale_rs::v2::setup_gs_base_for_ap(data as *const u8);
+///         assert_eq!((*stivale_rs::v2::current_cpu_data::<PerCpu>()).lapic_id, info.lapic_id);
+///     }
+///
+///     loop {}
+/// }
+/// ```
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn setup_gs_base_for_ap(per_cpu_data: *const u8) {
+    wrmsr(IA32_GS_BASE, per_cpu_data as u64);
+}
+
+/// Get the per CPU data of the calling CPU set up by [`setup_gs_base_for_ap`].
+///
+/// # Safety
+///
+/// Must be called in ring 0.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn current_cpu_data<T>() -> *const T {
+    rdmsr(IA32_GS_BASE) as *const T
+}
+
+/// Check the checksum of the ACPI table at `table_ptr`. All bytes of a valid table, as many as
+/// the `Length` field at offset 4 says, add up to zero. This should be checked before parsing
+/// any ACPI table.
+///
+/// # Safety
+///
+/// `table_ptr` must point to an ACPI table with a readable header and `Length` readable bytes.
+///
+/// # Example
+///
+/// ```
+/// let mut table = [0u8; 40];
+/// table[0..4].copy_from_slice(b"TEST");
+/// table[4..8].copy_from_slice(&40u32.to_le_bytes());
+/// table[36] = 0x12;
+///
+/// let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+/// table[9] = 0u8.wrapping_sub(sum);
+/// assert!(unsafe { stivale_rs::v2::validate_acpi_table_checksum(table.as_ptr()) });
+///
+/// table[36] = 0x13;
+/// assert!(!unsafe { stivale_rs::v2::validate_acpi_table_checksum(table.as_ptr()) });
+/// ```
+pub unsafe fn validate_acpi_table_checksum(table_ptr: *const u8) -> bool {
+    let length = core::ptr::read_unaligned(table_ptr.add(4) as *const u32) as usize;
+    let table = core::slice::from_raw_parts(table_ptr, length);
+
+    table.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LAPIC_LVT_TIMER: usize = 0x320;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LAPIC_TIMER_INITIAL_COUNT: usize = 0x380;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LAPIC_TIMER_CURRENT_COUNT: usize = 0x390;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LAPIC_TIMER_DIVIDE_CONFIG: usize = 0x3e0;
+/// Divide configuration register value for a divider of 16.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LAPIC_TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// Measure the frequency of the local APIC timer of the calling CPU with a divider of 16, using
+/// the HPET main counter as the reference over a 10ms window. If the HPET main counter isn't
+/// running it's started for the measurement and halted again afterwards.
+///
+/// Returns the frequency in Hz, to be passed to [`init_lapic_timer`], or 0 if the HPET doesn't
+/// report its counter period. Every CPU's timer runs at the same frequency, so this usually only
+/// has to be called once on the BSP.
+///
+/// # Safety
+///
+/// `lapic_base` and `hpet_base` must be the addresses of the memory mapped local APIC registers
+/// of the calling CPU and of the HPET registers.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn estimate_lapic_timer_freq(lapic_base: u64, hpet_base: u64) -> u64 {
+    const HPET_GENERAL_CAPABILITIES: usize = 0x000;
+    const HPET_GENERAL_CONFIGURATION: usize = 0x010;
+    const HPET_MAIN_COUNTER: usize = 0x0f0;
+    const HPET_COUNT_SIZE_CAP: u64 = 1 << 13;
+    const HPET_ENABLE_CNF: u64 = 1 << 0;
+    const LVT_MASKED: u32 = 1 << 16;
+    const FS_PER_SECOND: u128 = 1_000_000_000_000_000;
+    const WINDOW_FS: u64 = 10_000_000_000_000;
+
+    let lapic = |offset: usize| (lapic_base as usize + offset) as *mut u32;
+    let hpet = |offset: usize| (hpet_base as usize + offset) as *mut u64;
+
+    let capabilities = core::ptr::read_volatile(hpet(HPET_GENERAL_CAPABILITIES));
+    let counter_period = capabilities >> 32;
+    if counter_period == 0 {
+        return 0;
+    }
+
+    // A 32 bit main counter wraps after a few minutes, so the tick difference has to wrap at 32
+    // bits as well.
+    let counter_mask = if capabilities & HPET_COUNT_SIZE_CAP != 0 {
+        u64::MAX
+    } else {
+        u32::MAX as u64
+    };
+    let counter = || core::ptr::read_volatile(hpet(HPET_MAIN_COUNTER)) & counter_mask;
+
+    let config = core::ptr::read_volatile(hpet(HPET_GENERAL_CONFIGURATION));
+    if config & HPET_ENABLE_CNF == 0 {
+        core::ptr::write_volatile(hpet(HPET_GENERAL_CONFIGURATION), config | HPET_ENABLE_CNF);
+    }
+
+    core::ptr::write_volatile(lapic(LAPIC_TIMER_DIVIDE_CONFIG), LAPIC_TIMER_DIVIDE_BY_16);
+    core::ptr::write_volatile(lapic(LAPIC_LVT_TIMER), LVT_MASKED);
+
+    let window = core::cmp::max(WINDOW_FS / counter_period, 1);
+    let start = counter();
+    core::ptr::write_volatile(lapic(LAPIC_TIMER_INITIAL_COUNT), u32::MAX);
+
+    let ticks = loop {
+        let ticks = counter().wrapping_sub(start) & counter_mask;
+        if ticks >= window {
+            break ticks;
+        }
+
+        core::hint::spin_loop();
+    };
+
+    let elapsed = u32::MAX - core::ptr::read_volatile(lapic(LAPIC_TIMER_CURRENT_COUNT));
+    core::ptr::write_volatile(lapic(LAPIC_TIMER_INITIAL_COUNT), 0);
+
+    if config & HPET_ENABLE_CNF == 0 {
+        core::ptr::write_volatile(hpet(HPET_GENERAL_CONFIGURATION), config);
+    }
+
+    (elapsed as u128 * FS_PER_SECOND / (ticks as u128 * counter_period as u128)) as u64
+}
+
+/// Start the local APIC timer of the calling CPU, raising `vector` `frequency_hz` times a
+/// second, or once after `1 / frequency_hz` seconds if `oneshot` is set. `timer_freq` is the
+/// frequency of the timer returned by [`estimate_lapic_timer_freq`].
+///
+/// The timer is programmed in the following order: the divide configuration register is set to
+/// divide by 16, the LVT timer register is set to `vector` in periodic or one-shot mode, and
+/// finally the initial count register is written, which starts the timer.
+///
+/// The timer is left untouched if either frequency is 0.
+///
+/// # Safety
+///
+/// `lapic_base` must be the address of the memory mapped local APIC registers of the calling
+/// CPU.
+///
+/// # Example
+///
+/// ```no_run
+/// # let (lapic_base, hpet_base) = (0xfee00000, 0xfed00000);
+/// unsafe {
+///     let timer_freq = stivale_rs::v2::estimate_lapic_timer_freq(lapic_base, hpet_base);
+///     // Preempt every millisecond.
+///     stivale_rs::v2::init_lapic_timer(lapic_base, timer_freq, 1000, 0x20, false);
+/// }
+/// ```
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn init_lapic_timer(
+    lapic_base: u64,
+    timer_freq: u64,
+    frequency_hz: u64,
+    vector: u8,
+    oneshot: bool,
+) {
+    const LVT_PERIODIC: u32 = 1 << 17;
+
+    if timer_freq == 0 || frequency_hz == 0 {
+        return;
+    }
+
+    let lapic = |offset: usize| (lapic_base as usize + offset) as *mut u32;
+    let mode = if oneshot { 0 } else { LVT_PERIODIC };
+    let initial_count = (timer_freq / frequency_hz).clamp(1, u32::MAX as u64) as u32;
+
+    core::ptr::write_volatile(lapic(LAPIC_TIMER_DIVIDE_CONFIG), LAPIC_TIMER_DIVIDE_BY_16);
+    core::ptr::write_volatile(lapic(LAPIC_LVT_TIMER), vector as u32 | mode);
+    core::ptr::write_volatile(lapic(LAPIC_TIMER_INITIAL_COUNT), initial_count);
+}
+
+/// Find a `size` byte, `align` aligned hole in the physical address space that isn't covered by
+/// any memory map entry, to assign PCI BARs from. With `below_4gib` set, the hole ends below
+/// 4GiB so that it can back 32-bit BARs.
+///
+/// The lowest fitting hole above 1MiB is returned. Memory mapped firmware devices that are left
+/// out of the memory map, like the local APIC and IOAPIC, can't be detected here, so the result
+/// should still be checked against the ACPI tables. Returns `None` if no hole fits.
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::{reserve_mmio_aperture, Stivale2StructTagMemmap};
+///
+/// fn entry(memmap: &Stivale2StructTagMemmap<64>) {
+///     // A 256MiB window for 32-bit BARs.
+///     let base = reserve_mmio_aperture(memmap, 0x10000000, 0x10000000, true)
+///         .expect("no room for the aperture");
+///     // Assign BARs from `base`.
+/// }
+/// ```
+pub fn reserve_mmio_aperture<const ENTRIES_LEN: usize>(
+    memmap: &Stivale2StructTagMemmap<ENTRIES_LEN>,
+    size: u64,
+    align: u64,
+    below_4gib: bool,
+) -> Option<u64> {
+    const MIN_BASE: u64 = 0x100000;
+
+    let limit = if below_4gib { 0x100000000 } else { u64::MAX };
+    let entries = memmap.as_slice();
+
+    let candidates = core::iter::once(MIN_BASE).chain(
+        entries
+            .iter()
+            .map(|e| e.base.saturating_add(e.length))
+            .filter(|&end| end >= MIN_BASE),
+    );
+
+    candidates
+        .filter_map(|candidate| {
+            let start = align_up(candidate, align)?;
+            let end = start.checked_add(size)?;
+
+            let free = end <= limit
+                && entries
+                    .iter()
+                    .all(|e| e.base >= end || e.base.saturating_add(e.length) <= start);
+
+            if free {
+                Some(start)
+            } else {
+                None
+            }
+        })
+        .min()
+}
+
+/// The AP entry function passed to [`init_all_cpus`], called by [`init_all_cpus_trampoline`].
+#[cfg(target_arch = "x86_64")]
+static INIT_ALL_CPUS_ENTRY: core::sync::atomic::AtomicUsize =
+    core::sync::atomic::AtomicUsize::new(0);
+
+/// The entry function APs are woken up with by [`init_all_cpus`].
+#[cfg(target_arch = "x86_64")]
+extern "C" fn init_all_cpus_trampoline<T: Default>(info: *const Stivale2SmpInfo) -> ! {
+    let data = unsafe { &*info }
+        .extra_argument
+        .load(core::sync::atomic::Ordering::SeqCst) as *mut T;
+
+    unsafe {
+        setup_gs_base_for_ap(data as *const u8);
+        data.write(T::default());
+    }
+
+    let entry = INIT_ALL_CPUS_ENTRY.load(core::sync::atomic::Ordering::SeqCst);
+    let entry = unsafe { core::mem::transmute::<usize, fn(*const Stivale2SmpInfo) -> !>(entry) };
+    entry(info)
+}
+
+/// Set up per CPU data of type `T` on every CPU and start the APs at `ap_entry`.
+///
+/// For every CPU, `alloc` is called with its LAPIC ID to allocate its `T` and the pointer is
+/// stored in `extra_argument`. The BSP initializes its own data and GS base right away. Every AP
+/// is woken up with an entry function that points its GS base at its data, initializes it with
+/// `T::default()` and then calls `ap_entry`, so the data is available through
+/// [`current_cpu_data`] from the start.
+///
+/// This is meant to be called once during boot, as `ap_entry` is shared by every call.
+///
+/// # Safety
+///
+/// Must be called in ring 0 on the BSP. `alloc` must return pointers that are valid for writes
+/// of `T` and live forever, and `target_stack` has to be set up for every AP beforehand.
+///
+/// # Example
+///
+/// ```no_run
+/// use stivale_rs::v2::{current_cpu_data, init_all_cpus, Stivale2SmpInfo, Stivale2StructTagSmp};
+///
+/// #[derive(Clone, Copy, Default)]
+/// struct PerCpu {
+///     ticks: u64,
+/// }
+///
+/// static mut PER_CPU: [PerCpu; 4] = [PerCpu { ticks: 0 }; 4];
+///
+/// fn ap_entry(_info: *const Stivale2SmpInfo) -> ! {
+///     let data = unsafe { &*current_cpu_data::<PerCpu>() };
+///     assert_eq!(data.ticks, 0);
+///     loop {}
+/// }
+///
+/// fn start(smp: &Stivale2StructTagSmp<4>) {
+///     unsafe {
+///         init_all_cpus(smp, |lapic_id| &raw mut PER_CPU[lapic_id as usize], ap_entry);
+///     }
+/// }
+/// ```
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn init_all_cpus<T: Default, A: FnMut(u32) -> *mut T, const CPU_COUNT: usize>(
+    smp: &Stivale2StructTagSmp<CPU_COUNT>,
+    mut alloc: A,
+    ap_entry: fn(*const Stivale2SmpInfo) -> !,
+) {
+    use core::sync::atomic::Ordering;
+
+    INIT_ALL_CPUS_ENTRY.store(ap_entry as usize, Ordering::SeqCst);
+
+    for cpu in smp.as_slice() {
+        let data = alloc(cpu.lapic_id);
+
+        if cpu.lapic_id == smp.bsp_lapic_id {
+            setup_gs_base_for_ap(data as *const u8);
+            data.write(T::default());
+            continue;
+        }
+
+        // The AP reads `extra_argument` once it's woken up, so it must be written first.
+        cpu.extra_argument.store(data as u64, Ordering::SeqCst);
+
+        cpu.goto(init_all_cpus_trampoline::<T>);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// A memory map with room for `N` entries, holding `entries`.
+    fn memmap<const N: usize>(
+        entries: &[(u64, u64, Stivale2MMapType)],
+    ) -> Stivale2StructTagMemmap<N> {
+        let empty = Stivale2MMapEntry {
+            base: 0,
+            length: 0,
+            r#type: 0,
+            unsed: 0,
+        };
+        let mut memmap = Stivale2StructTagMemmap {
+            identifier: STIVALE2_STRUCT_TAG_MEMMAP_ID,
+            next: 0,
+            entries: entries.len() as u64,
+            memmap: [empty; N],
+        };
+
+        for (slot, &(base, length, r#type)) in memmap.memmap.iter_mut().zip(entries) {
+            *slot = Stivale2MMapEntry {
+                base,
+                length,
+                r#type: r#type as u32,
+                unsed: 0,
+            };
+        }
+
+        memmap
+    }
+
+    /// Get the `(base, length, type)` of every entry of `memmap`.
+    fn ranges<const N: usize>(memmap: &Stivale2StructTagMemmap<N>) -> Vec<(u64, u64, u32)> {
+        memmap
+            .as_slice()
+            .iter()
+            .map(|e| (e.base, e.length, e.r#type))
+            .collect()
+    }
+
+    /// A 32bpp framebuffer backed by `pixels`, with rows `pitch` bytes apart.
+    fn framebuffer(
+        pixels: &mut [u32],
+        width: u16,
+        height: u16,
+        pitch: u16,
+    ) -> Stivale2StructTagFramebuffer {
+        Stivale2StructTagFramebuffer {
+            identifier: STIVALE2_STRUCT_TAG_FRAMEBUFFER_ID,
+            next: 0,
+            framebuffer_addr: pixels.as_mut_ptr() as u64,
+            framebuffer_width: width,
+            framebuffer_height: height,
+            framebuffer_pitch: pitch,
+            framebuffer_bpp: 32,
+            memory_model: 1,
+            red_mask_size: 8,
+            red_mask_shift: 16,
+            green_mask_size: 8,
+            green_mask_shift: 8,
+            blue_mask_size: 8,
+            blue_mask_shift: 0,
+        }
+    }
+
+    #[test]
+    fn is_range_usable_spans_adjacent_entries() {
+        let map = memmap::<3>(&[
+            (0x0000, 0x1000, Stivale2MMapType::Usable),
+            (0x1000, 0x1000, Stivale2MMapType::Usable),
+            (0x2000, 0x1000, Stivale2MMapType::Reserved),
+        ]);
+
+        assert!(map.is_range_usable(0x0800, 0x1000));
+        assert!(!map.is_range_usable(0x1800, 0x1000));
+        assert!(!map.is_range_usable(0x2000, 0x0800));
+    }
+
+    #[test]
+    fn reserve_range_splits_usable_entries() {
+        let mut map = memmap::<4>(&[
+            (0x1000, 0x4000, Stivale2MMapType::Usable),
+            (0x5000, 0x3000, Stivale2MMapType::Usable),
+        ]);
+
+        assert!(map.reserve_range(0x6000, 0x1000, Stivale2MMapType::KernelAndModules));
+        assert_eq!(
+            ranges(&map),
+            [
+                (0x1000, 0x4000, Stivale2MMapType::Usable as u32),
+                (0x5000, 0x1000, Stivale2MMapType::Usable as u32),
+                (0x6000, 0x1000, Stivale2MMapType::KernelAndModules as u32),
+                (0x7000, 0x1000, Stivale2MMapType::Usable as u32),
+            ]
+        );
+    }
+
+    #[test]
+    fn reserve_range_keeps_entries_past_capacity() {
+        // Only 2 of the 3 entries the bootloader reported fit in the map.
+        let mut map = memmap::<2>(&[
+            (0x1000, 0x4000, Stivale2MMapType::Usable),
+            (0x7000, 0x1000, Stivale2MMapType::Usable),
+        ]);
+        map.entries = 3;
+
+        assert!(!map.reserve_range(0x5000, 0x1000, Stivale2MMapType::KernelAndModules));
+        assert_eq!(map.entries, 3);
+    }
+
+    #[test]
+    fn reserve_modules_and_kernel_marks_both() {
+        let mut map = memmap::<4>(&[(0x100000, 0x100000, Stivale2MMapType::Usable)]);
+        let modules = Stivale2StructTagModules {
+            identifier: STIVALE2_STRUCT_TAG_MODULES_ID,
+            next: 0,
+            module_count: 1,
+            modules: [Stivale2Module {
+                begin: 0x180000,
+                end: 0x181000,
+                string: [0; 128],
+            }],
+        };
+        let kernel_base = Stivale2StructTagKernelBaseAddress {
+            identifier: STIVALE2_STRUCT_TAG_KERNEL_BASE_ADDRESS_ID,
+            next: 0,
+            physical_base_address: 0x100000,
+            virtual_base_address: 0xffffffff80000000,
+        };
+
+        assert!(reserve_modules_and_kernel(
+            &mut map,
+            &modules,
+            &kernel_base,
+            0x10000
+        ));
+        assert_eq!(
+            ranges(&map),
+            [
+                (0x100000, 0x10000, Stivale2MMapType::KernelAndModules as u32),
+                (0x110000, 0x70000, Stivale2MMapType::Usable as u32),
+                (0x180000, 0x1000, Stivale2MMapType::KernelAndModules as u32),
+                (0x181000, 0x7f000, Stivale2MMapType::Usable as u32),
+            ]
+        );
+    }
+
+    #[test]
+    fn reserve_modules_and_kernel_is_all_or_nothing() {
+        // The module fits, but splitting for the kernel as well needs 5 entries.
+        let mut map = memmap::<4>(&[(0x100000, 0x100000, Stivale2MMapType::Usable)]);
+        let modules = Stivale2StructTagModules {
+            identifier: STIVALE2_STRUCT_TAG_MODULES_ID,
+            next: 0,
+            module_count: 1,
+            modules: [Stivale2Module {
+                begin: 0x180000,
+                end: 0x181000,
+                string: [0; 128],
+            }],
+        };
+        let kernel_base = Stivale2StructTagKernelBaseAddress {
+            identifier: STIVALE2_STRUCT_TAG_KERNEL_BASE_ADDRESS_ID,
+            next: 0,
+            physical_base_address: 0x110000,
+            virtual_base_address: 0xffffffff80000000,
+        };
+
+        assert!(!reserve_modules_and_kernel(
+            &mut map,
+            &modules,
+            &kernel_base,
+            0x10000
+        ));
+        assert_eq!(
+            ranges(&map),
+            [(0x100000, 0x100000, Stivale2MMapType::Usable as u32)]
+        );
+    }
+
+    #[test]
+    fn carve_heap_skips_low_memory_and_aligns() {
+        let map = memmap::<3>(&[
+            (0x1000, 0x9f000, Stivale2MMapType::Usable),
+            (0x100000, 0x80000, Stivale2MMapType::KernelAndModules),
+            (0x180800, 0x100000, Stivale2MMapType::Usable),
+        ]);
+
+        let (start, end) = map.carve_heap(0x10000, 0x1000).unwrap();
+        assert_eq!((start, end), (0x181000, 0x191000));
+        assert!(map.is_range_usable(start, end - start));
+        assert!(map.carve_heap(0x200000, 0x1000).is_none());
+    }
+
+    #[test]
+    fn draw_line_clips_to_the_framebuffer() {
+        let mut pixels = [0u32; 16];
+        let fb = framebuffer(&mut pixels, 4, 4, 16);
+
+        fb.draw_line(-2, -2, 10, 10, 0xffffff);
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x == y { 0xffffff } else { 0 };
+                assert_eq!(pixels[y * 4 + x], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn draw_line_only_steps_through_the_visible_part() {
+        let mut pixels = [0u32; 16];
+        let fb = framebuffer(&mut pixels, 4, 4, 16);
+
+        fb.draw_line(i32::MIN, 1, i32::MAX, 1, 0x00ff00);
+
+        for (idx, &pixel) in pixels.iter().enumerate() {
+            let expected = if idx / 4 == 1 { 0x00ff00 } else { 0 };
+            assert_eq!(pixel, expected);
+        }
+    }
+
+    #[test]
+    fn pixel_writer_matches_put_pixel() {
+        let mut expected = [0u32; 256];
+        let mut actual = [0u32; 256];
+        let mut expected_fb = framebuffer(&mut expected, 16, 16, 64);
+        let mut actual_fb = framebuffer(&mut actual, 16, 16, 64);
+
+        // An unusual pixel format, with uneven mask sizes.
+        for fb in [&mut expected_fb, &mut actual_fb] {
+            fb.green_mask_size = 6;
+            fb.blue_mask_size = 10;
+        }
+
+        let writer = actual_fb.pixel_writer();
+
+        for y in 0..16u16 {
+            for x in 0..16u16 {
+                let (r, g, b) = ((x * 16) as u8, (y * 16) as u8, (x * y) as u8);
+                expected_fb.put_pixel(x, y, r, g, b);
+                writer.put(x, y, r, g, b);
+            }
+        }
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn check_stack_safety_checks_the_guard_page() {
+        let map = memmap::<3>(&[
+            (0x000000, 0x100000, Stivale2MMapType::Reserved),
+            (0x100000, 0x100000, Stivale2MMapType::KernelAndModules),
+            (0x200000, 0x100000, Stivale2MMapType::Usable),
+        ]);
+
+        assert_eq!(
+            check_stack_safety(&map, 0x100000, 0x4000),
+            StackSafetyResult::Safe
+        );
+        assert_eq!(
+            check_stack_safety(&map, 0x201000, 0x4000),
+            StackSafetyResult::GuardPageOverlapsUsable {
+                overlapping_entry: 2
+            }
+        );
+        assert_eq!(
+            check_stack_safety(&map, 0x0f0000, 0x20000),
+            StackSafetyResult::StackInNonUsableRegion
+        );
+    }
+
+    #[test]
+    fn render_char_uses_the_unicode_table() {
+        let mut font = Vec::new();
+        for field in [0x864ab572u32, 0, 32, 1, 2, 2, 2, 8] {
+            font.extend_from_slice(&field.to_le_bytes());
+        }
+        // Glyph 0 is blank, glyph 1 has its leftmost column set.
+        font.extend_from_slice(&[0x00, 0x00, 0x80, 0x80]);
+        font.extend_from_slice(&[0xff, b'A', 0xff]);
+
+        let font = Stivale2Psf2Font::new(&font).unwrap();
+        let mut pixels = [0u32; 16];
+        let fb = framebuffer(&mut pixels, 8, 2, 32);
+
+        font.render_char(&fb, 'A', 0, 0, 0xffffff, 0x111111);
+
+        for row in pixels.chunks(8) {
+            assert_eq!(row[0], 0xffffff);
+            assert!(row[1..].iter().all(|&p| p == 0x111111));
+        }
+    }
+
+    #[test]
+    fn to_e820_maps_types_and_truncates() {
+        let map = memmap::<3>(&[
+            (0x0000, 0x1000, Stivale2MMapType::Usable),
+            (0x1000, 0x1000, Stivale2MMapType::ACPINvs),
+            (0x2000, 0x1000, Stivale2MMapType::KernelAndModules),
+        ]);
+
+        let mut buf = [E820Entry::default(); 2];
+        assert_eq!(to_e820(&map, &mut buf), 2);
+        assert_eq!({ buf[0].type_ }, 1);
+        assert_eq!({ buf[1].base }, 0x1000);
+        assert_eq!({ buf[1].type_ }, 4);
+
+        let mut buf = [E820Entry::default(); 3];
+        assert_eq!(to_e820(&map, &mut buf), 3);
+        assert_eq!({ buf[2].type_ }, 2);
+    }
+
+    #[test]
+    fn usable_regions_aligned_ignores_other_entries() {
+        let mut map = memmap::<2>(&[
+            (0x0000, 0x1000, Stivale2MMapType::Usable),
+            (0x1000, 0x0800, Stivale2MMapType::Reserved),
+        ]);
+        assert!(map.usable_regions_aligned(0x1000));
+        assert!(!map.usable_regions_aligned(0));
+
+        map.memmap[1].r#type = Stivale2MMapType::Usable as u32;
+        assert!(!map.usable_regions_aligned(0x1000));
+    }
+
+    #[test]
+    fn reserve_kernel_huge_page_aligned_expands_the_range() {
+        let mut map = memmap::<3>(&[(0, 0x800000, Stivale2MMapType::Usable)]);
+
+        let range = reserve_kernel_huge_page_aligned(&mut map, 0x280000, 0x410000);
+        assert_eq!(range, Some((0x200000, 0x600000)));
+        assert!(!map.is_range_usable(0x200000, 0x400000));
+        assert!(map.is_range_usable(0x600000, 0x200000));
+    }
+
+    #[test]
+    fn copy_to_skips_row_padding() {
+        // A 2x2 framebuffer with a pitch of 3 pixels.
+        let mut pixels = [1u32, 2, 0, 3, 4, 0];
+        let fb = framebuffer(&mut pixels, 2, 2, 12);
+
+        let mut screenshot = [0u32; 4];
+        assert_eq!(fb.copy_to(&mut screenshot), 4);
+        assert_eq!(screenshot, [1, 2, 3, 4]);
+
+        let mut partial = [0u32; 3];
+        assert_eq!(fb.copy_to(&mut partial), 3);
+        assert_eq!(partial, [1, 2, 3]);
+    }
+
+    #[test]
+    fn draw_progress_bar_fills_in_proportion() {
+        let mut pixels = [0u32; 12 * 3];
+        let fb = framebuffer(&mut pixels, 12, 3, 48);
+
+        fb.draw_progress_bar(0, 0, 12, 3, 50, 0xf, 0xb, 0xe);
+
+        assert!(pixels[..12].iter().all(|&p| p == 0xe));
+        assert_eq!(
+            pixels[12..24],
+            [0xe, 0xf, 0xf, 0xf, 0xf, 0xf, 0xb, 0xb, 0xb, 0xb, 0xb, 0xe]
+        );
+        assert!(pixels[24..].iter().all(|&p| p == 0xe));
+    }
+
+    #[test]
+    fn usable_excluding_splits_entries() {
+        let map = memmap::<2>(&[
+            (0x1000, 0x4000, Stivale2MMapType::Usable),
+            (0x5000, 0x1000, Stivale2MMapType::Reserved),
+        ]);
+        let ranges = |base, len| map.usable_excluding(base, len).collect::<Vec<_>>();
+
+        assert_eq!(ranges(0x1000, 0x1000), [(0x2000, 0x5000)]);
+        assert_eq!(ranges(0x2000, 0x1000), [(0x1000, 0x2000), (0x3000, 0x5000)]);
+        assert_eq!(ranges(0x4000, 0x2000), [(0x1000, 0x4000)]);
+        assert_eq!(ranges(0x8000, 0x1000), [(0x1000, 0x5000)]);
+        assert_eq!(ranges(0x2000, 0), [(0x1000, 0x5000)]);
+    }
+
+    #[test]
+    fn blit_rect_clips_to_the_framebuffer() {
+        let mut pixels = [0u32; 4 * 2];
+        let fb = framebuffer(&mut pixels, 4, 2, 16);
+        let src: Vec<u8> = [1u32, 2, 3, 4]
+            .iter()
+            .flat_map(|p| p.to_ne_bytes())
+            .collect();
+
+        fb.blit_rect(&src, 8, 3, 0, 2, 2);
+        assert_eq!(pixels, [0, 0, 0, 1, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn blit_rect_stops_when_the_source_offset_overflows() {
+        let mut pixels = [0u32; 4 * 2];
+        let fb = framebuffer(&mut pixels, 4, 2, 16);
+        let src: Vec<u8> = [1u32, 2, 3, 4]
+            .iter()
+            .flat_map(|p| p.to_ne_bytes())
+            .collect();
+
+        // The second row would start past the end of the address space, so it's left out.
+        fb.blit_rect(&src, usize::MAX, 0, 0, 2, 2);
+        assert_eq!(pixels, [1, 2, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn blit_rect_dispatch_copies_every_pixel() {
+        // Rows of 15 pixels cover both the 32 byte chunks and the remainder of the AVX2 path.
+        let mut pixels = [0u32; 16 * 2];
+        let fb = framebuffer(&mut pixels, 16, 2, 64);
+        let src: Vec<u8> = (0..30u32).flat_map(|p| p.to_ne_bytes()).collect();
+
+        fb.blit_rect_dispatch(&src, 60, 1, 0, 15, 2);
+
+        assert_eq!(pixels[0], 0);
+        assert!((1..16).all(|x| pixels[x] == x as u32 - 1));
+        assert!((1..16).all(|x| pixels[16 + x] == x as u32 + 14));
+    }
+
+    #[test]
+    fn framebuffer_overlaps_usable_checks_the_whole_framebuffer() {
+        let mut fb = framebuffer(&mut [], 1024, 768, 4096);
+        fb.framebuffer_addr = 0xfd000000;
+        let info = Stivale2Struct {
+            bootloader_brand: [0; 64],
+            bootloader_version: [0; 64],
+            tags: &fb as *const _ as u64,
+        };
+
+        let framebuffer_map = memmap::<1>(&[(0xfd000000, 0x300000, Stivale2MMapType::Framebuffer)]);
+        let usable_map = memmap::<1>(&[(0xfd2ff000, 0x1000, Stivale2MMapType::Usable)]);
+
+        assert!(!info.framebuffer_overlaps_usable(&framebuffer_map));
+        assert!(info.framebuffer_overlaps_usable(&usable_map));
+    }
+
+    #[test]
+    fn clear_with_aligned_rows() {
+        // 8 byte aligned rows with one pixel of padding.
+        let mut aligned = [0u64; 4];
+        let mut fb = framebuffer(&mut [], 3, 2, 16);
+        fb.framebuffer_addr = aligned.as_mut_ptr() as u64;
+
+        fb.clear(0xaabbcc);
+
+        let pixels: Vec<u32> = aligned
+            .iter()
+            .flat_map(|&p| [p as u32, (p >> 32) as u32])
+            .collect();
+        assert_eq!(
+            pixels,
+            [0xaabbcc, 0xaabbcc, 0xaabbcc, 0, 0xaabbcc, 0xaabbcc, 0xaabbcc, 0]
+        );
+    }
+
+    #[test]
+    fn clear_with_unaligned_rows() {
+        let mut pixels = [0u32; 6];
+        framebuffer(&mut pixels, 3, 2, 12).clear(0xaabbcc);
+        assert_eq!(pixels, [0xaabbcc; 6]);
+    }
+
+    #[test]
+    fn reserve_mmio_aperture_finds_holes() {
+        let map = memmap::<3>(&[
+            (0x100000, 0x7ff00000, Stivale2MMapType::Usable),
+            (0x80000000, 0x7ff00000, Stivale2MMapType::Reserved),
+            (0x100000000, 0x80000000, Stivale2MMapType::Usable),
+        ]);
+
+        assert_eq!(
+            reserve_mmio_aperture(&map, 0x100000, 0x100000, true),
+            Some(0xfff00000)
+        );
+        assert_eq!(reserve_mmio_aperture(&map, 0x200000, 0x100000, true), None);
+        assert_eq!(
+            reserve_mmio_aperture(&map, 0x200000, 0x100000, false),
+            Some(0x180000000)
+        );
+    }
+
+    #[test]
+    fn index_of_finds_the_containing_entry() {
+        let map = memmap::<2>(&[
+            (0x0000, 0x1000, Stivale2MMapType::Reserved),
+            (0x1000, 0x1000, Stivale2MMapType::Usable),
+        ]);
+
+        assert_eq!(map.index_of(0x0fff), Some(0));
+        assert_eq!(map.index_of(0x1800), Some(1));
+        assert_eq!(map.index_of(0x2000), None);
+        assert_eq!(map.find(0x1800).unwrap().base, 0x1000);
+    }
+
+    #[test]
+    fn draw_palette_swap_follows_the_palette() {
+        let mut pixels = [0xffu32; 4];
+        let fb = framebuffer(&mut pixels, 4, 1, 16);
+
+        let mut palette = Stivale2Palette { colors: [0; 256] };
+        for (i, color) in palette.colors.iter_mut().enumerate() {
+            *color = i as u32 * 10;
+        }
+
+        let frame = [0, 1, 2, 255];
+        fb.draw_palette_swap(&frame, 4, 1, 0, 0, &palette, 0);
+        assert_eq!(pixels, [0xff, 10, 20, 2550]);
+
+        animate_palette(&mut palette, 1);
+        fb.draw_palette_swap(&frame, 4, 1, 0, 0, &palette, 0);
+        assert_eq!(pixels, [0xff, 2550, 10, 2540]);
+    }
+
+    #[test]
+    fn nvs_regions_only_counts_acpi_nvs() {
+        let map = memmap::<3>(&[
+            (0x0000, 0x1000, Stivale2MMapType::ACPINvs),
+            (0x1000, 0x1000, Stivale2MMapType::Usable),
+            (0x2000, 0x3000, Stivale2MMapType::ACPINvs),
+        ]);
+
+        assert_eq!(map.nvs_regions().count(), 2);
+        assert_eq!(map.total_nvs_size(), 0x4000);
+    }
+
+    #[test]
+    fn usable_below_cuts_entries_at_the_limit() {
+        let map = memmap::<3>(&[
+            (0x100000, 0x100000, Stivale2MMapType::Usable),
+            (0xc0000000, 0x80000000, Stivale2MMapType::Usable),
+            (0x140000000, 0x100000, Stivale2MMapType::Usable),
+        ]);
+
+        let ranges: Vec<_> = map.usable_below(0x100000000).collect();
+        assert_eq!(ranges, [(0x100000, 0x200000), (0xc0000000, 0x100000000)]);
+    }
+
+    #[test]
+    fn layout_hash_tracks_order_and_contents() {
+        let low = (0x0000, 0x1000, Stivale2MMapType::Reserved);
+        let high = (0x1000, 0x1000, Stivale2MMapType::Usable);
+        let changed = (0x1000, 0x2000, Stivale2MMapType::Usable);
+        let hash = memmap::<2>(&[low, high]).layout_hash();
+
+        assert_eq!(memmap::<2>(&[low, high]).layout_hash(), hash);
+        assert_ne!(memmap::<2>(&[high, low]).layout_hash(), hash);
+        assert_ne!(memmap::<2>(&[low, changed]).layout_hash(), hash);
+    }
+
+    #[test]
+    fn config_hash_tracks_the_cmdline() {
+        let map = memmap::<1>(&[(0x100000, 0x100000, Stivale2MMapType::Usable)]);
+        let cmdline = |cmdline: &[u8]| Stivale2StructTagCmdline {
+            identifier: STIVALE2_STRUCT_TAG_CMDLINE_ID,
+            next: &map as *const _ as u64,
+            cmdline: cmdline.as_ptr() as u64,
+        };
+        let info = |cmdline: &Stivale2StructTagCmdline| Stivale2Struct {
+            bootloader_brand: [b'a'; 64],
+            bootloader_version: [b'1'; 64],
+            tags: cmdline as *const _ as u64,
+        };
+
+        let first = cmdline(b"quiet\0");
+        let second = cmdline(b"quiet\0");
+        let changed = cmdline(b"verbose\0");
+
+        assert_eq!(info(&first).config_hash(), info(&second).config_hash());
+        assert_ne!(info(&first).config_hash(), info(&changed).config_hash());
+    }
 }